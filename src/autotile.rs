@@ -0,0 +1,257 @@
+use crate::prelude::*;
+use bevy::prelude::{Quat, Transform, Vec3};
+
+const NORTH: u8 = 1;
+const EAST: u8 = 2;
+const SOUTH: u8 = 4;
+const WEST: u8 = 8;
+
+const WALL_THICKNESS: f32 = 0.4;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WallKind {
+    Pillar,
+    End,
+    Edge,
+    Corner,
+    TJunction,
+    Cross,
+    Interior,
+}
+
+impl WallKind {
+    // Neighbour mask of each kind before it is rotated into place
+    fn canonical_mask(self) -> u8 {
+        match self {
+            WallKind::Pillar => 0,
+            WallKind::End => NORTH,
+            WallKind::Edge => NORTH | SOUTH,
+            WallKind::Corner => NORTH | EAST,
+            WallKind::TJunction => NORTH | EAST | SOUTH,
+            WallKind::Cross | WallKind::Interior => NORTH | EAST | SOUTH | WEST,
+        }
+    }
+
+    fn from_mask(mask: u8) -> Self {
+        match mask.count_ones() {
+            0 => WallKind::Pillar,
+            1 => WallKind::End,
+            2 if mask == NORTH | SOUTH || mask == EAST | WEST => WallKind::Edge,
+            2 => WallKind::Corner,
+            3 => WallKind::TJunction,
+            _ => WallKind::Cross,
+        }
+    }
+
+    /// Slab pieces making up the wall model, in the canonical (unrotated) orientation.
+    /// Each piece is an offset and a scale applied to the unit tile.
+    pub fn pieces(self) -> Vec<(Vec3, Vec3)> {
+        if self == WallKind::Interior {
+            return vec![(Vec3::ZERO, Vec3::ONE)];
+        }
+
+        let mut pieces = vec![(Vec3::ZERO, Vec3::new(WALL_THICKNESS, 1.0, WALL_THICKNESS))];
+        let mask = self.canonical_mask();
+        let arm_length = (1.0 - WALL_THICKNESS) / 2.0;
+        let arm_offset = (WALL_THICKNESS + arm_length) / 2.0;
+        if mask & NORTH != 0 {
+            pieces.push((
+                Vec3::new(0.0, 0.0, -arm_offset),
+                Vec3::new(WALL_THICKNESS, 1.0, arm_length),
+            ));
+        }
+        if mask & EAST != 0 {
+            pieces.push((
+                Vec3::new(arm_offset, 0.0, 0.0),
+                Vec3::new(arm_length, 1.0, WALL_THICKNESS),
+            ));
+        }
+        if mask & SOUTH != 0 {
+            pieces.push((
+                Vec3::new(0.0, 0.0, arm_offset),
+                Vec3::new(WALL_THICKNESS, 1.0, arm_length),
+            ));
+        }
+        if mask & WEST != 0 {
+            pieces.push((
+                Vec3::new(-arm_offset, 0.0, 0.0),
+                Vec3::new(arm_length, 1.0, WALL_THICKNESS),
+            ));
+        }
+        pieces
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WallTile {
+    pub kind: WallKind,
+    pub mask: u8,
+    // Clockwise quarter turns, as seen from above
+    pub quarter_turns: u8,
+}
+
+impl WallTile {
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(-(self.quarter_turns as f32) * std::f32::consts::FRAC_PI_2)
+    }
+
+    /// Local transforms of every piece, ready to be spawned as children of the wall entity.
+    pub fn piece_transforms(&self) -> Vec<Transform> {
+        let rotation = self.rotation();
+        self.kind
+            .pieces()
+            .into_iter()
+            .map(|(offset, scale)| Transform {
+                translation: rotation * offset,
+                rotation,
+                scale,
+            })
+            .collect()
+    }
+}
+
+fn rotate_mask(mask: u8) -> u8 {
+    ((mask << 1) | (mask >> 3)) & 0xF
+}
+
+fn is_wall(map: &Map, point: Point) -> bool {
+    match map.try_idx(point) {
+        Some(idx) => matches!(map.tiles[idx], TileType::Wall | TileType::HiddenDoor),
+        None => true,
+    }
+}
+
+pub fn wall_mask(map: &Map, point: Point) -> u8 {
+    let mut mask = 0;
+    if is_wall(map, point + Point::new(0, -1)) {
+        mask |= NORTH;
+    }
+    if is_wall(map, point + Point::new(1, 0)) {
+        mask |= EAST;
+    }
+    if is_wall(map, point + Point::new(0, 1)) {
+        mask |= SOUTH;
+    }
+    if is_wall(map, point + Point::new(-1, 0)) {
+        mask |= WEST;
+    }
+    mask
+}
+
+pub fn wall_tile(map: &Map, point: Point) -> WallTile {
+    let mask = wall_mask(map, point);
+
+    let surrounded = (-1..=1)
+        .flat_map(|y| (-1..=1).map(move |x| Point::new(x, y)))
+        .all(|delta| is_wall(map, point + delta));
+    if surrounded {
+        return WallTile {
+            kind: WallKind::Interior,
+            mask,
+            quarter_turns: 0,
+        };
+    }
+
+    let kind = WallKind::from_mask(mask);
+    let mut canonical = kind.canonical_mask();
+    let mut quarter_turns = 0;
+    while canonical != mask && quarter_turns < 4 {
+        canonical = rotate_mask(canonical);
+        quarter_turns += 1;
+    }
+
+    WallTile {
+        kind,
+        mask,
+        quarter_turns: quarter_turns % 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A floor map with walls at `walls`
+    fn map_with_walls(walls: &[(i32, i32)]) -> Map {
+        let mut map = Map::with_size(7, 7);
+        for (x, y) in walls {
+            let idx = map.idx(*x, *y);
+            map.tiles[idx] = TileType::Wall;
+        }
+        map
+    }
+
+    #[test]
+    fn rotating_a_mask_four_times_gives_it_back() {
+        assert_eq!(rotate_mask(NORTH), EAST);
+        assert_eq!(rotate_mask(WEST), NORTH);
+        for mask in 0..16 {
+            let turned = (0..4).fold(mask, |m, _| rotate_mask(m));
+            assert_eq!(turned, mask);
+        }
+    }
+
+    #[test]
+    fn every_mask_is_a_rotated_canonical_kind() {
+        for mask in 0..16u8 {
+            let kind = WallKind::from_mask(mask);
+            let turned = (0..4)
+                .scan(kind.canonical_mask(), |m, _| {
+                    let current = *m;
+                    *m = rotate_mask(*m);
+                    Some(current)
+                })
+                .any(|m| m == mask);
+            assert!(turned, "mask {mask:04b} is not a rotation of {kind:?}");
+        }
+    }
+
+    #[test]
+    fn lone_walls_are_pillars() {
+        let map = map_with_walls(&[(3, 3)]);
+        let pillar = wall_tile(&map, Point::new(3, 3));
+        assert_eq!(pillar.kind, WallKind::Pillar);
+        assert_eq!(pillar.quarter_turns, 0);
+    }
+
+    #[test]
+    fn wall_tiles_pick_kind_and_rotation_from_neighbours() {
+        // A horizontal run of wall is an edge turned a quarter from north-south
+        let map = map_with_walls(&[(2, 3), (3, 3), (4, 3)]);
+        let middle = wall_tile(&map, Point::new(3, 3));
+        assert_eq!(middle.kind, WallKind::Edge);
+        assert_eq!(middle.mask, EAST | WEST);
+        assert_eq!(middle.quarter_turns, 1);
+        let end = wall_tile(&map, Point::new(4, 3));
+        assert_eq!(end.kind, WallKind::End);
+        assert_eq!(end.mask, WEST);
+        assert_eq!(end.quarter_turns, 3);
+
+        // Walls running south and east from (3, 3) make a corner turned once
+        let map = map_with_walls(&[(3, 3), (4, 3), (3, 4)]);
+        let corner = wall_tile(&map, Point::new(3, 3));
+        assert_eq!(corner.kind, WallKind::Corner);
+        assert_eq!(corner.mask, EAST | SOUTH);
+        assert_eq!(corner.quarter_turns, 1);
+    }
+
+    #[test]
+    fn walls_surrounded_by_walls_are_interior() {
+        let walls: Vec<(i32, i32)> = (2..=4).flat_map(|y| (2..=4).map(move |x| (x, y))).collect();
+        let map = map_with_walls(&walls);
+        assert_eq!(wall_tile(&map, Point::new(3, 3)).kind, WallKind::Interior);
+        assert_eq!(wall_tile(&map, Point::new(3, 2)).kind, WallKind::TJunction);
+        // Off the map counts as wall, so the map edge is solid
+        let map = map_with_walls(&[]);
+        assert_eq!(wall_mask(&map, Point::new(0, 0)), NORTH | WEST);
+        assert_eq!(wall_mask(&map, Point::new(3, 6)), SOUTH);
+    }
+
+    #[test]
+    fn hidden_doors_tile_like_walls() {
+        let mut map = map_with_walls(&[(2, 3), (4, 3)]);
+        let idx = map.idx(3, 3);
+        map.tiles[idx] = TileType::HiddenDoor;
+        assert_eq!(wall_tile(&map, Point::new(2, 3)).mask, EAST);
+    }
+}
@@ -0,0 +1,368 @@
+use bevy_sword::prelude::*;
+use serde_json::json;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const USAGE: &str = "\
+Usage: mapgen [options]
+
+Options:
+    --seed <n>           First seed to generate (default: current time)
+    --count <n>          Number of consecutive seeds to generate (default: 1)
+    --architect <name>   random | empty | random-walk | rooms | automata (default: random)
+    --corridors <layout> sorted | mst | mst-loops | nearest, for rooms (default: sorted)
+    --routing <routing>  l-shaped | astar | drunken, for rooms (default: l-shaped)
+    --corridor-width <n> Corridor width for rooms, at least 1 (default: 1)
+    --width <n>          Map width in tiles, at least 24 (default: 80)
+    --height <n>         Map height in tiles, at least 16 (default: 50)
+    --depth <n>          Dungeon depth used for spawn tables (default: 1)
+    --exits <exits>      none | stairs | <n> exits per level above the last (default: stairs)
+    --secret-rooms <n>   Rooms hidden behind secret doors (default: 1)
+    --monster-density <f> Share of the floor with a monster, 0 to 1 (default: per architect)
+    --format <format>    ascii | json | png (default: ascii)
+    --tile-size <px>     Pixels per tile for png output (default: 8)
+    --output <dir>       Write one file per map instead of printing to stdout
+";
+
+const MIN_WIDTH: i32 = 24;
+const MIN_HEIGHT: i32 = 16;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    Json,
+    Png,
+}
+
+struct Options {
+    seed: u64,
+    count: u64,
+    architect: Option<ArchitectKind>,
+    corridors: CorridorStyle,
+    depth: i32,
+    exits: ExitPlacement,
+    secret_rooms: usize,
+    monster_density: Option<f32>,
+    width: i32,
+    height: i32,
+    format: Format,
+    tile_size: u32,
+    output: Option<PathBuf>,
+}
+
+fn parse_architect(name: &str) -> Result<Option<ArchitectKind>, String> {
+    match name {
+        "random" => Ok(None),
+        "empty" => Ok(Some(ArchitectKind::Empty)),
+        "random-walk" => Ok(Some(ArchitectKind::RandomWalk)),
+        "rooms" => Ok(Some(ArchitectKind::Rooms(CorridorStyle::default()))),
+        "automata" => Ok(Some(ArchitectKind::CellularAutomata)),
+        _ => Err(format!("unknown architect '{name}'")),
+    }
+}
+
+fn parse_layout(name: &str) -> Result<CorridorLayout, String> {
+    match name {
+        "sorted" => Ok(CorridorLayout::SortedByX),
+        "mst" => Ok(CorridorLayout::MinimumSpanningTree { extra_loops: 0 }),
+        "mst-loops" => Ok(CorridorLayout::MinimumSpanningTree { extra_loops: 3 }),
+        "nearest" => Ok(CorridorLayout::NearestNeighbour),
+        _ => Err(format!("unknown corridor layout '{name}'")),
+    }
+}
+
+fn parse_routing(name: &str) -> Result<CorridorRouting, String> {
+    match name {
+        "l-shaped" => Ok(CorridorRouting::LShaped),
+        "astar" => Ok(CorridorRouting::AStar),
+        "drunken" => Ok(CorridorRouting::Drunken),
+        _ => Err(format!("unknown corridor routing '{name}'")),
+    }
+}
+
+fn parse_exits(value: &str) -> Result<ExitPlacement, String> {
+    match value {
+        "none" => Ok(ExitPlacement::None),
+        "stairs" => Ok(ExitPlacement::Stairs),
+        _ => Ok(ExitPlacement::Branching {
+            count: parse_number("--exits", value)?,
+        }),
+    }
+}
+
+fn parse_format(name: &str) -> Result<Format, String> {
+    match name {
+        "ascii" => Ok(Format::Ascii),
+        "json" => Ok(Format::Json),
+        "png" => Ok(Format::Png),
+        _ => Err(format!("unknown format '{name}'")),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{flag} expects a number, got '{value}'"))
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        seed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        count: 1,
+        architect: None,
+        corridors: CorridorStyle::default(),
+        depth: 1,
+        exits: ExitPlacement::Stairs,
+        secret_rooms: 1,
+        monster_density: None,
+        width: SCREEN_WIDTH,
+        height: SCREEN_HEIGHT,
+        format: Format::Ascii,
+        tile_size: 8,
+        output: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        if flag == "--help" || flag == "-h" {
+            print!("{USAGE}");
+            process::exit(0);
+        }
+        if !matches!(
+            flag.as_str(),
+            "--seed"
+                | "--count"
+                | "--architect"
+                | "--corridors"
+                | "--routing"
+                | "--corridor-width"
+                | "--depth"
+                | "--exits"
+                | "--secret-rooms"
+                | "--monster-density"
+                | "--width"
+                | "--height"
+                | "--format"
+                | "--tile-size"
+                | "--output"
+        ) {
+            return Err(format!("unknown option '{flag}'"));
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{flag} expects a value"))?;
+        match flag.as_str() {
+            "--seed" => options.seed = parse_number(&flag, &value)?,
+            "--count" => options.count = parse_number(&flag, &value)?,
+            "--architect" => options.architect = parse_architect(&value)?,
+            "--corridors" => options.corridors.layout = parse_layout(&value)?,
+            "--routing" => options.corridors.routing = parse_routing(&value)?,
+            "--corridor-width" => options.corridors.width = parse_number(&flag, &value)?,
+            "--depth" => options.depth = parse_number(&flag, &value)?,
+            "--exits" => options.exits = parse_exits(&value)?,
+            "--secret-rooms" => options.secret_rooms = parse_number(&flag, &value)?,
+            "--monster-density" => options.monster_density = Some(parse_number(&flag, &value)?),
+            "--width" => options.width = parse_number(&flag, &value)?,
+            "--height" => options.height = parse_number(&flag, &value)?,
+            "--format" => options.format = parse_format(&value)?,
+            "--tile-size" => options.tile_size = parse_number(&flag, &value)?,
+            _ => options.output = Some(PathBuf::from(value)),
+        }
+    }
+
+    if options.count > 0 && options.seed.checked_add(options.count - 1).is_none() {
+        return Err("--seed plus --count runs past the largest seed".to_string());
+    }
+    if options.monster_density.is_some_and(|d| !(0.0..=1.0).contains(&d)) {
+        return Err("--monster-density must be between 0 and 1".to_string());
+    }
+    if options.corridors.width < 1 {
+        return Err("--corridor-width must be at least 1".to_string());
+    }
+    // Smaller maps leave the architects and the fortress prefab no room to work in
+    if options.width < MIN_WIDTH || options.height < MIN_HEIGHT {
+        return Err(format!("maps must be at least {MIN_WIDTH}x{MIN_HEIGHT} tiles"));
+    }
+    if let Some(ArchitectKind::Rooms(corridors)) = &mut options.architect {
+        *corridors = options.corridors;
+    }
+    if options.format == Format::Png && options.output.is_none() {
+        return Err("png output needs --output <dir>".to_string());
+    }
+    Ok(options)
+}
+
+fn generate(seed: u64, options: &Options) -> Result<MapBuilder, MapGenError> {
+    let mut rng = RandomNumberGenerator::seeded(seed);
+    let settings = GenerationSettings {
+        depth: options.depth,
+        exits: options.exits,
+        secret_rooms: options.secret_rooms,
+        monster_density: options.monster_density,
+        width: options.width,
+        height: options.height,
+        ..GenerationSettings::default()
+    };
+    let kind = options
+        .architect
+        .unwrap_or_else(|| ArchitectKind::random(&mut rng));
+    MapBuilder::with_settings(kind, &mut rng, &settings)
+}
+
+fn glyph(mb: &MapBuilder, point: Point) -> char {
+    if point == mb.player_start {
+        '@'
+    } else if point == mb.amulet_start {
+        '|'
+    } else if mb.has_monster_at(point) {
+        'M'
+    } else if mb.has_loot_at(point) {
+        '$'
+    } else {
+        match mb.map.tiles[mb.map.point2d_to_index(point)] {
+            TileType::Wall => '#',
+            TileType::Floor => '.',
+            TileType::Exit => '>',
+            TileType::Door => '+',
+            TileType::OpenDoor => '\'',
+            TileType::HiddenDoor => 'S',
+        }
+    }
+}
+
+fn to_ascii(mb: &MapBuilder) -> Vec<String> {
+    (0..mb.map.height)
+        .map(|y| (0..mb.map.width).map(|x| glyph(mb, Point::new(x, y))).collect())
+        .collect()
+}
+
+fn to_json(seed: u64, mb: &MapBuilder) -> serde_json::Value {
+    let point = |p: &Point| json!([p.x, p.y]);
+    let room = |r: &Room| json!({
+        "bounds": [r.bounds.x1, r.bounds.y1, r.bounds.width(), r.bounds.height()],
+        "shape": format!("{:?}", r.shape),
+        "tiles": r.tiles.iter().map(point).collect::<Vec<_>>(),
+    });
+    let metrics = mb.metrics();
+    json!({
+        "seed": seed,
+        "width": mb.map.width,
+        "height": mb.map.height,
+        "tiles": to_ascii(mb),
+        "player_start": point(&mb.player_start),
+        "amulet_start": point(&mb.amulet_start),
+        "monster_spawns": mb.monster_spawns.iter().map(|s| json!({
+            "position": point(&s.position),
+            "kind": format!("{:?}", s.kind),
+        })).collect::<Vec<_>>(),
+        "loot": mb.loot.iter().map(|l| json!({
+            "position": point(&l.position),
+            "kind": format!("{:?}", l.kind),
+        })).collect::<Vec<_>>(),
+        "rooms": mb.rooms.iter().map(room).collect::<Vec<_>>(),
+        "secret_rooms": mb.secret_rooms.iter().map(room).collect::<Vec<_>>(),
+        "metrics": {
+            "floor_ratio": metrics.floor_ratio,
+            "regions": metrics.regions,
+            "start_to_amulet": metrics.start_to_amulet,
+            "dead_ends": metrics.dead_ends,
+            "exits": metrics.exits,
+            "room_count": metrics.room_count,
+            "room_sizes": metrics.room_sizes,
+            "spawn_density": metrics.spawn_density,
+        },
+    })
+}
+
+fn write_png(mb: &MapBuilder, tile_size: u32, path: &Path) -> Result<(), String> {
+    let mut image = image::RgbImage::new(
+        mb.map.width as u32 * tile_size,
+        mb.map.height as u32 * tile_size,
+    );
+    for y in 0..mb.map.height {
+        for x in 0..mb.map.width {
+            let color = match glyph(mb, Point::new(x, y)) {
+                '#' => [40, 40, 48],
+                '.' => [160, 150, 130],
+                '>' => [60, 120, 220],
+                '+' | '\'' => [130, 80, 30],
+                'S' => [140, 60, 180],
+                '@' => [60, 200, 60],
+                '|' => [230, 200, 40],
+                'M' => [200, 50, 50],
+                '$' => [250, 140, 0],
+                _ => [255, 0, 255],
+            };
+            for py in 0..tile_size {
+                for px in 0..tile_size {
+                    image.put_pixel(
+                        x as u32 * tile_size + px,
+                        y as u32 * tile_size + py,
+                        image::Rgb(color),
+                    );
+                }
+            }
+        }
+    }
+    image
+        .save(path)
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    if let Some(dir) = &options.output {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    }
+
+    // The last seed is checked to fit in `parse_args`
+    for seed in (0..options.count).map(|i| options.seed + i) {
+        let mb = match generate(seed, options) {
+            Ok(mb) => mb,
+            Err(error) => {
+                eprintln!("mapgen: seed {seed} failed to generate: {error}");
+                continue;
+            }
+        };
+        let (extension, contents) = match options.format {
+            Format::Ascii => ("txt", to_ascii(&mb).join("\n") + "\n"),
+            Format::Json => ("json", to_json(seed, &mb).to_string() + "\n"),
+            Format::Png => ("png", String::new()),
+        };
+
+        match &options.output {
+            Some(dir) => {
+                let path = dir.join(format!("map_{seed}.{extension}"));
+                if options.format == Format::Png {
+                    write_png(&mb, options.tile_size, &path)?;
+                } else {
+                    std::fs::write(&path, contents)
+                        .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+                }
+            }
+            None => {
+                if options.format == Format::Ascii {
+                    println!("seed {seed}");
+                }
+                print!("{contents}");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args().and_then(|options| run(&options));
+    if let Err(message) = result {
+        eprintln!("mapgen: {message}");
+        eprint!("{USAGE}");
+        process::exit(1);
+    }
+}
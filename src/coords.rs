@@ -0,0 +1,96 @@
+//! Conversions between map grid points and world space.
+//!
+//! Grid x runs along world x and grid y along world z, one tile per `TILE_SIZE`.
+//! The camera looks down the world x axis, so screen up is +x and screen right is +z.
+use crate::prelude::*;
+use bevy::prelude::{Vec2, Vec3};
+
+pub const TILE_SIZE: f32 = 1.0;
+
+/// The centre of `point` at floor level.
+pub fn grid_to_world(point: Point) -> Vec3 {
+    Vec3::new(point.x as f32 * TILE_SIZE, 0.0, point.y as f32 * TILE_SIZE)
+}
+
+/// The tile `translation` stands on, whatever its height.
+pub fn world_to_grid(translation: Vec3) -> Point {
+    Point::new(
+        (translation.x / TILE_SIZE).round() as i32,
+        (translation.z / TILE_SIZE).round() as i32,
+    )
+}
+
+/// Distance between two points along the floor, ignoring height.
+pub fn floor_distance(a: Vec3, b: Vec3) -> f32 {
+    Vec2::new(a.x - b.x, a.z - b.z).length()
+}
+
+/// How far a tile's slab is raised above floor level.
+pub fn tile_height(tile: TileType) -> f32 {
+    match tile {
+        TileType::Wall | TileType::HiddenDoor => 0.2,
+        TileType::Floor | TileType::Door | TileType::OpenDoor => 0.0,
+        TileType::Exit => -0.2,
+    }
+}
+
+/// Where the slab for the tile at `point` sits.
+pub fn tile_translation(map: &Map, point: Point) -> Vec3 {
+    grid_to_world(point) + Vec3::Y * tile_height(map.tiles[map.point2d_to_index(point)])
+}
+
+/// Turns a direction on screen, y up, into a direction along the floor.
+pub fn screen_to_world(direction: Vec2) -> Vec3 {
+    Vec3::new(direction.y, 0.0, direction.x)
+}
+
+/// Turns a direction along the floor into a direction on screen, y up.
+pub fn world_to_screen(direction: Vec3) -> Vec2 {
+    Vec2::new(direction.z, direction.x)
+}
+
+/// The middle of the map at floor level.
+pub fn map_center() -> Vec3 {
+    grid_to_world(Point::new(SCREEN_WIDTH / 2, SCREEN_HEIGHT / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_points_survive_a_trip_through_world_space() {
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let point = Point::new(x, y);
+                assert_eq!(world_to_grid(grid_to_world(point)), point);
+            }
+        }
+    }
+
+    #[test]
+    fn world_positions_round_to_the_nearest_tile() {
+        let point = Point::new(4, 7);
+        let centre = grid_to_world(point);
+        for offset in [Vec3::new(0.49, 0.0, 0.0), Vec3::new(-0.3, 2.0, 0.45)] {
+            assert_eq!(world_to_grid(centre + offset * TILE_SIZE), point);
+        }
+        assert_eq!(world_to_grid(centre + Vec3::X * 0.6 * TILE_SIZE), Point::new(5, 7));
+    }
+
+    #[test]
+    fn screen_directions_survive_a_trip_through_world_space() {
+        for direction in [Vec2::X, Vec2::Y, Vec2::new(-0.6, 0.8)] {
+            assert_eq!(world_to_screen(screen_to_world(direction)), direction);
+        }
+        // Screen up looks down the world x axis, screen right along z
+        assert_eq!(screen_to_world(Vec2::Y), Vec3::X);
+        assert_eq!(screen_to_world(Vec2::X), Vec3::Z);
+    }
+
+    #[test]
+    fn floor_distance_ignores_height() {
+        let a = Vec3::new(1.0, 0.0, 1.0);
+        assert_eq!(floor_distance(a, Vec3::new(4.0, 9.0, 5.0)), 5.0);
+    }
+}
@@ -0,0 +1,23 @@
+#[cfg(feature = "render")]
+pub mod autotile;
+#[cfg(feature = "render")]
+pub mod coords;
+pub mod map;
+pub mod map_builder;
+
+pub mod prelude {
+    pub use bracket_lib::prelude::{
+        a_star_search, field_of_view_set, Algorithm2D, BaseMap, DijkstraMap, DistanceAlg,
+        Point, RandomNumberGenerator, Rect, SmallVec,
+    };
+    pub const SCREEN_WIDTH: i32 = 80;
+    pub const SCREEN_HEIGHT: i32 = 50;
+    // The amulet waits on the last level
+    pub const FINAL_DEPTH: i32 = 3;
+    #[cfg(feature = "render")]
+    pub use crate::autotile::*;
+    #[cfg(feature = "render")]
+    pub use crate::coords::*;
+    pub use crate::map::*;
+    pub use crate::map_builder::*;
+}
@@ -6,35 +6,106 @@ pub use bevy_inspector_egui::quick::WorldInspectorPlugin;
 pub use leafwing_input_manager::prelude::*;
 pub use leafwing_input_manager::{errors::NearlySingularConversion, orientation::Direction};
 pub use bevy_mod_picking::*;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+};
 
 
-mod map;
-mod map_builder;
-
-mod prelude {
-    pub use bracket_lib::prelude::{
-        Algorithm2D, BaseMap, DijkstraMap, DistanceAlg, Point, RandomNumberGenerator, Rect,
-        SmallVec,
-    };
-    pub const SCREEN_WIDTH: i32 = 80;
-    pub const SCREEN_HEIGHT: i32 = 50;
-    pub const MAP_SIZE_WIDTH: usize = 14;
-    pub const MAP_SIZE_HEIGHT: usize = 21;
-    pub use crate::map::*;
-    pub use crate::map_builder::*;
-}
-
-use prelude::*;
+use bevy_sword::prelude::*;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 enum GameState {
     Playing,
     GameOver,
+    Victory,
 }
 
 #[derive(Component)]
 struct Player;
 
+#[derive(Component)]
+struct MapTile;
+
+#[derive(Component)]
+struct Monster {
+    kind: MonsterKind,
+}
+
+// The tile a monster is currently stepping onto, reserved so no other monster takes it
+#[derive(Component, Default)]
+struct Movement {
+    step: Option<Point>,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+struct Health {
+    current: i32,
+    max: i32,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+struct Damage(i32);
+
+#[derive(Component, Clone, Copy, Debug)]
+struct Defense(i32);
+
+#[derive(Component)]
+struct AttackCooldown(Timer);
+
+#[derive(Component)]
+struct Scoreboard;
+
+#[derive(Component, Clone, Copy, Debug)]
+struct Stats {
+    max_health: i32,
+    attack: i32,
+    defense: i32,
+    // Tiles per second
+    speed: f32,
+}
+
+impl Stats {
+    fn for_monster(kind: MonsterKind) -> Self {
+        match kind {
+            MonsterKind::Goblin => Stats { max_health: 6, attack: 2, defense: 0, speed: 3.0 },
+            MonsterKind::Orc => Stats { max_health: 12, attack: 4, defense: 1, speed: 2.5 },
+            MonsterKind::Skeleton => Stats { max_health: 10, attack: 3, defense: 2, speed: 2.0 },
+            MonsterKind::Ogre => Stats { max_health: 25, attack: 7, defense: 3, speed: 1.5 },
+        }
+    }
+}
+
+#[derive(Component)]
+struct Amulet;
+
+#[derive(Component)]
+struct Door {
+    position: Point,
+}
+
+#[derive(Component)]
+struct Wall {
+    position: Point,
+}
+
+#[derive(Component)]
+struct Pickup {
+    kind: LootKind,
+}
+
+#[derive(Component, Default)]
+struct Inventory {
+    items: Vec<LootKind>,
+}
+
+// Entities that open closed doors by walking into them
+#[derive(Component)]
+struct OpensDoors;
+
+#[derive(Component)]
+struct SnapshotTile;
+
 #[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug)]
 enum ArpgAction {
     // Movement
@@ -48,6 +119,7 @@ enum ArpgAction {
     Ability3,
     Ability4,
     Ultimate,
+    Search,
 }
 
 impl ArpgAction {
@@ -116,6 +188,9 @@ impl PlayerBundle {
         input_map.insert(KeyCode::R, Ultimate);
         input_map.insert(GamepadButtonType::LeftTrigger2, Ultimate);
 
+        input_map.insert(KeyCode::S, Search);
+        input_map.insert(GamepadButtonType::RightTrigger, Search);
+
         input_map
     }
 }
@@ -124,19 +199,32 @@ impl PlayerBundle {
 struct Game {
     map: Map,
     score: i32,
+    // 0 until the first level of a run is set up
+    depth: i32,
+    run_started: f64,
+    run_finished: f64,
     camera_should_focus: Vec3,
     camera_is_focus: Vec3,
 }
 
-const RESET_FOCUS: [f32; 3] = [
-    MAP_SIZE_HEIGHT as f32 / 2.0,
-    0.0,
-    MAP_SIZE_WIDTH as f32 / 2.0 - 0.5,
-];
+// Intermediate maps of the current level, replayed with F1 in debug builds
+#[derive(Default, Resource)]
+struct GenerationReplay {
+    snapshots: Vec<Map>,
+    frame: usize,
+    timer: Timer,
+    playing: bool,
+}
+
+// Behind and above the focus, looking down the world x axis
+const CAMERA_OFFSET: Vec3 = Vec3::new(-10.0, 14.0, 0.0);
 
 fn main() {
     App::new()
         .init_resource::<Game>()
+        .init_resource::<GenerationReplay>()
+        .init_resource::<FlowField>()
+        .insert_resource(AiRng(RandomNumberGenerator::new()))
         .add_plugins(DefaultPlugins)
         .add_plugin(InputManagerPlugin::<ArpgAction>::default())
         .add_plugin(LogDiagnosticsPlugin::default())
@@ -151,42 +239,113 @@ fn main() {
         .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup))
         .add_system_set(
             SystemSet::on_update(GameState::Playing)
+                .with_system(replay_generation)
+                .with_system(open_doors)
+                .with_system(search_for_secrets)
+                .with_system(pick_up_loot)
+                .with_system(pick_up_amulet)
+                .with_system(take_exit)
+                .with_system(focus_camera)
+                .with_system(update_flow_field)
+                .with_system(monster_ai.after(update_flow_field))
+                .with_system(monster_attacks)
+                .with_system(apply_damage.after(monster_attacks))
+                .with_system(handle_deaths.after(apply_damage))
+                .with_system(update_scoreboard)
+                .with_system(use_abilities)
+                .with_system(apply_ability_effects.after(use_abilities).before(apply_damage))
+                .with_system(move_projectiles.before(apply_damage))
+                .with_system(fade_explosions)
+                .with_system(dash_movement)
+                .with_system(expire_invulnerability)
                 //.with_system(move_player)
                 // .with_system(camera_movement_system),
                 // .with_system(movement)
                 // .with_system(player_input)
         )
-        .add_startup_system(spawn_player)
-        // The ActionState can be used directly
+        // Effects of cast abilities
         .add_system(cast_fireball)
-        // Or multiple parts of it can be inspected
         .add_system(player_dash)
+        .add_system(show_dash_trail)
         // Or it can be used to emit events for later processing
         .add_event::<PlayerWalk>()
+        .add_event::<DamageEvent>()
+        .add_event::<DeathEvent>()
+        .add_event::<AbilityEvent>()
+        .add_event::<DashEvent>()
         .add_system(player_walks)
         .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(teardown))
+        .add_system_set(
+            SystemSet::on_enter(GameState::Victory)
+                .with_system(show_victory)
+                .with_system(despawn_player),
+        )
+        .add_system_set(SystemSet::on_update(GameState::Victory).with_system(restart_on_enter))
+        .add_system_set(SystemSet::on_exit(GameState::Victory).with_system(teardown))
+        .add_system_set(
+            SystemSet::on_enter(GameState::GameOver)
+                .with_system(show_game_over)
+                .with_system(despawn_player),
+        )
+        .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(restart_on_enter))
+        .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(teardown))
         .add_system(bevy::window::close_on_esc)
         .run();
 }
 
 fn setup_cameras(mut commands: Commands, mut game: ResMut<Game>) {
-    game.camera_should_focus = Vec3::from(RESET_FOCUS);
+    game.camera_should_focus = map_center();
     game.camera_is_focus = game.camera_should_focus;
     commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(
-                -(MAP_SIZE_WIDTH as f32 / 2.0),
-                2.0 * MAP_SIZE_HEIGHT as f32 / 3.0,
-                MAP_SIZE_HEIGHT as f32 / 2.0 - 0.5,
-        )
-        .looking_at(game.camera_is_focus, Vec3::Y),
+        transform: Transform::from_translation(game.camera_is_focus + CAMERA_OFFSET)
+            .looking_at(game.camera_is_focus, Vec3::Y),
         ..default()
     });
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut game: ResMut<Game>) {
-    let mut rng = RandomNumberGenerator::new();
-    let map_builder = MapBuilder::new(&mut rng);
-    game.score = 0;
+fn focus_camera(
+    time: Res<Time>,
+    mut game: ResMut<Game>,
+    player: Query<&Transform, (With<Player>, Without<Camera3d>)>,
+    mut cameras: Query<&mut Transform, (With<Camera3d>, Without<Player>)>,
+) {
+    const SPEED: f32 = 2.0;
+    if let Ok(player) = player.get_single() {
+        game.camera_should_focus = player.translation;
+    }
+    let motion = game.camera_should_focus - game.camera_is_focus;
+    game.camera_is_focus += motion * (SPEED * time.delta_seconds()).min(1.0);
+    for mut transform in &mut cameras {
+        *transform = Transform::from_translation(game.camera_is_focus + CAMERA_OFFSET)
+            .looking_at(game.camera_is_focus, Vec3::Y);
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut game: ResMut<Game>,
+    mut player: Query<(Entity, &mut Transform), With<Player>>,
+) {
+    if game.depth == 0 {
+        game.depth = 1;
+        game.score = 0;
+        game.run_started = time.elapsed_seconds_f64();
+    }
+
+    let settings = GenerationSettings {
+        depth: game.depth,
+        record_snapshots: cfg!(debug_assertions),
+        ..default()
+    };
+    let mut map_builder = generate_level(&settings);
+
+    commands.insert_resource(GenerationReplay {
+        snapshots: map_builder.snapshots.take().unwrap_or_default(),
+        timer: Timer::from_seconds(0.1, TimerMode::Repeating),
+        ..default()
+    });
 
     game.map = map_builder.map;
 
@@ -207,43 +366,101 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut game: ResMu
         .iter()
         .enumerate()
         .for_each(|(idx, tile)| match tile {
-            TileType::Wall => {
-                commands.spawn(SceneBundle {
-                    transform: Transform::from_xyz(
-                        game.map.index_to_point2d(idx).x as f32,
-                        0.2,
-                        game.map.index_to_point2d(idx).y as f32,
-                    ),
-                    scene: cell_scene.clone(),
-                    ..default()
-                }).insert(PickableBundle::default());
+            TileType::Wall | TileType::HiddenDoor => {
+                spawn_wall(&mut commands, &cell_scene, &game.map, game.map.index_to_point2d(idx));
             }
-            TileType::Floor => {
+            TileType::Floor | TileType::Exit => {
                 commands.spawn(SceneBundle {
-                    transform: Transform::from_xyz(
-                        game.map.index_to_point2d(idx).x as f32,
-                        0.,
-                        game.map.index_to_point2d(idx).y as f32,
+                    transform: Transform::from_translation(
+                        tile_translation(&game.map, game.map.index_to_point2d(idx)),
                     ),
                     scene: cell_scene.clone(),
                     ..default()
-                }).insert(PickableBundle::default());
+                }).insert((MapTile, PickableBundle::default()));
             }
-            TileType::Exit => {
-                commands.spawn(SceneBundle {
-                    transform: Transform::from_xyz(
-                        game.map.index_to_point2d(idx).x as f32,
-                        -0.2,
-                        game.map.index_to_point2d(idx).y as f32,
-                    ),
-                    scene: cell_scene.clone(),
-                    ..default()
-                }).insert(PickableBundle::default());
+            TileType::Door | TileType::OpenDoor => {
+                spawn_door(&mut commands, &cell_scene, &game.map, game.map.index_to_point2d(idx));
             }
         });
 
+    if game.depth == FINAL_DEPTH {
+        let amulet = map_builder.amulet_start;
+        commands.spawn(SceneBundle {
+            transform: Transform::from_translation(grid_to_world(amulet) + Vec3::Y * 0.3)
+                .with_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_4))
+                .with_scale(Vec3::new(0.4, 2.0, 0.4)),
+            scene: cell_scene.clone(),
+            ..default()
+        }).insert((MapTile, Amulet));
+    }
+
+    for loot in map_builder.loot.iter() {
+        commands.spawn(SceneBundle {
+            transform: Transform::from_translation(grid_to_world(loot.position) + Vec3::Y * 0.2)
+                .with_scale(Vec3::new(0.3, 1.0, 0.3)),
+            scene: cell_scene.clone(),
+            ..default()
+        }).insert((MapTile, Pickup { kind: loot.kind }));
+    }
+
+    // Patrols start where the monster stands and take in a couple of rooms. Maps without
+    // rooms patrol between reachable floor tiles near the monster instead
+    let flow_field = FlowField::new(&game.map, map_builder.player_start);
+    let reachable: Vec<Point> = if map_builder.rooms.is_empty() {
+        (0..game.map.tiles.len())
+            .map(|idx| game.map.index_to_point2d(idx))
+            .filter(|p| {
+                game.map.tiles[game.map.point2d_to_index(*p)] == TileType::Floor
+                    && flow_field.distance(&game.map, *p) < f32::MAX
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut rng = RandomNumberGenerator::new();
+    for spawn in map_builder.monster_spawns.iter() {
+        let waypoints: Vec<Point> = if map_builder.rooms.is_empty() {
+            reachable
+                .iter()
+                .copied()
+                .filter(|p| {
+                    DistanceAlg::Pythagoras.distance2d(*p, spawn.position) <= PATROL_RADIUS
+                })
+                .collect()
+        } else {
+            map_builder.rooms.iter().map(|room| room.center()).collect()
+        };
+        let mut route = vec![spawn.position];
+        for _ in 0..2 {
+            if let Some(waypoint) = rng.random_slice_entry(&waypoints) {
+                route.push(*waypoint);
+            }
+        }
+        spawn_monster(&mut commands, &asset_server, spawn, route);
+    }
+
+    // The player carries health, mana, inventory and abilities down the stairs, and is only
+    // spawned afresh at the start of a run
+    if let Ok((entity, mut transform)) = player.get_single_mut() {
+        transform.translation = grid_to_world(map_builder.player_start);
+        commands.entity(entity).remove::<Dashing>();
+    } else {
+        spawn_player(&mut commands, &asset_server, map_builder.player_start);
+    }
+    commands.insert_resource(flow_field);
+    game.camera_should_focus = grid_to_world(map_builder.player_start);
+    game.camera_is_focus = game.camera_should_focus;
+
+    for prop in map_builder.props.iter() {
+        commands.spawn(SceneBundle {
+            transform: prop_transform(prop),
+            scene: cell_scene.clone(),
+            ..default()
+        }).insert(MapTile);
+    }
+
     // scoreboard
-    commands.spawn(
+    commands.spawn((
         TextBundle::from_section(
             "Score:",
             TextStyle {
@@ -261,26 +478,854 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut game: ResMu
             },
             ..default()
         }),
+        Scoreboard,
+    ));
+}
+
+// Sent to hurt `target`; defense is taken off before it lands
+struct DamageEvent {
+    target: Entity,
+    amount: i32,
+}
+
+// Sent once when something's health runs out
+struct DeathEvent {
+    entity: Entity,
+}
+
+// Seconds between two attacks of the same monster
+const ATTACK_INTERVAL: f32 = 1.0;
+const PLAYER_HEALTH: i32 = 30;
+
+fn monster_score(kind: MonsterKind) -> i32 {
+    match kind {
+        MonsterKind::Goblin => 5,
+        MonsterKind::Orc => 10,
+        MonsterKind::Skeleton => 15,
+        MonsterKind::Ogre => 40,
+    }
+}
+
+fn monster_attacks(
+    time: Res<Time>,
+    game: Res<Game>,
+    flow_field: Res<FlowField>,
+    player: Query<Entity, With<Player>>,
+    mut monsters: Query<(&Monster, &Transform, &Damage, &mut AttackCooldown)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    for (monster, transform, damage, mut cooldown) in &mut monsters {
+        cooldown.0.tick(time.delta());
+        if !cooldown.0.finished() {
+            continue;
+        }
+        let position = world_to_grid(transform.translation);
+        let distance = flow_field.distance(&game.map, position);
+        // Ranged monsters shoot from where they keep their distance, if they can see the player
+        let in_reach = match Temperament::for_monster(monster.kind).keep_distance {
+            Some(keep) => distance <= keep + 1.0 && flow_field.visible.contains(&position),
+            None => distance <= 1.0,
+        };
+        if in_reach {
+            damage_events.send(DamageEvent { target: player, amount: damage.0 });
+            cooldown.0.reset();
+        }
+    }
+}
+
+fn apply_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut targets: Query<(&mut Health, Option<&Defense>), Without<Invulnerable>>,
+) {
+    for event in damage_events.iter() {
+        let Ok((mut health, defense)) = targets.get_mut(event.target) else {
+            continue;
+        };
+        if health.current <= 0 {
+            continue;
+        }
+        // Armour never turns a hit into nothing
+        let dealt = (event.amount - defense.map_or(0, |d| d.0)).max(1);
+        health.current -= dealt;
+        if health.current <= 0 {
+            death_events.send(DeathEvent { entity: event.target });
+        }
+    }
+}
+
+fn handle_deaths(
+    mut commands: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    mut state: ResMut<State<GameState>>,
+    mut game: ResMut<Game>,
+    monsters: Query<&Monster>,
+    player: Query<(), With<Player>>,
+) {
+    for event in death_events.iter() {
+        if let Ok(monster) = monsters.get(event.entity) {
+            game.score += monster_score(monster.kind);
+            commands.entity(event.entity).despawn_recursive();
+        } else if player.contains(event.entity) {
+            let _ = state.set(GameState::GameOver);
+        }
+    }
+}
+
+fn update_scoreboard(
+    game: Res<Game>,
+    player: Query<(&Health, &Mana), With<Player>>,
+    mut scoreboards: Query<&mut Text, With<Scoreboard>>,
+) {
+    let (health, mana) = player
+        .get_single()
+        .map_or((0, 0.0), |(health, mana)| (health.current, mana.current));
+    for mut text in &mut scoreboards {
+        text.sections[0].value =
+            format!("Score: {}  Health: {health}  Mana: {mana:.0}", game.score);
+    }
+}
+
+// Monsters give chase from this many steps away, or from anywhere the player can see them
+const CHASE_RANGE: f32 = 12.0;
+const SIGHT_RANGE: i32 = 8;
+
+// Walking distance to the player from every tile, shared by all chasing monsters
+#[derive(Resource)]
+struct FlowField {
+    origin: Point,
+    distances: Vec<f32>,
+    // Lower is safer, see `flee_field`
+    flee: Vec<f32>,
+    visible: HashSet<Point>,
+}
+
+// Nobody chases anything until `setup` builds the first real field
+impl Default for FlowField {
+    fn default() -> Self {
+        Self {
+            origin: Point::zero(),
+            distances: Vec::new(),
+            flee: Vec::new(),
+            visible: HashSet::new(),
+        }
+    }
+}
+
+impl FlowField {
+    fn new(map: &Map, origin: Point) -> Self {
+        let dijkstra_map = DijkstraMap::new(
+            map.width,
+            map.height,
+            &[map.point2d_to_index(origin)],
+            map,
+            map.width as f32,
+        );
+        Self {
+            origin,
+            flee: flee_field(map, &dijkstra_map.map),
+            distances: dijkstra_map.map,
+            visible: field_of_view_set(origin, SIGHT_RANGE, map),
+        }
+    }
+
+    fn flee_distance(&self, map: &Map, point: Point) -> f32 {
+        map.try_idx(point)
+            .and_then(|idx| self.flee.get(idx).copied())
+            .unwrap_or(f32::MAX)
+    }
+
+    fn distance(&self, map: &Map, point: Point) -> f32 {
+        map.try_idx(point)
+            .and_then(|idx| self.distances.get(idx).copied())
+            .unwrap_or(f32::MAX)
+    }
+
+    fn should_chase(&self, map: &Map, point: Point) -> bool {
+        self.distance(map, point) <= CHASE_RANGE || self.visible.contains(&point)
+    }
+}
+
+// A tile waiting in the flee field's open set, ordered so the heap pops the cheapest first
+#[derive(PartialEq)]
+struct FleeStep {
+    cost: f32,
+    idx: usize,
+}
+
+impl Eq for FleeStep {}
+
+impl Ord for FleeStep {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for FleeStep {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The chase distances scaled past zero and settled again, so walking downhill leads away
+// from the player towards open ground rather than into the nearest corner. Every reachable
+// tile starts out seeded with its scaled distance, so a single Dijkstra pass settles them all
+fn flee_field(map: &Map, distances: &[f32]) -> Vec<f32> {
+    let mut flee: Vec<f32> = distances
+        .iter()
+        .map(|d| if *d < f32::MAX { -1.2 * d } else { f32::MAX })
+        .collect();
+    let mut open: BinaryHeap<FleeStep> = flee
+        .iter()
+        .enumerate()
+        .filter(|(_, cost)| **cost < f32::MAX)
+        .map(|(idx, cost)| FleeStep { cost: *cost, idx })
+        .collect();
+    while let Some(FleeStep { cost, idx }) = open.pop() {
+        // Already settled more cheaply through another tile
+        if cost > flee[idx] {
+            continue;
+        }
+        for (next, step) in map.get_available_exits(idx) {
+            if cost + step < flee[next] {
+                flee[next] = cost + step;
+                open.push(FleeStep { cost: cost + step, idx: next });
+            }
+        }
+    }
+    flee
+}
+
+fn update_flow_field(
+    walks: EventReader<PlayerWalk>,
+    game: Res<Game>,
+    mut flow_field: ResMut<FlowField>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let walked = !walks.is_empty();
+    walks.clear();
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let position = world_to_grid(player.translation);
+    if walked && position != flow_field.origin {
+        *flow_field = FlowField::new(&game.map, position);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Behaviour {
+    Wander,
+    Patrol,
+    Chase,
+    Flee,
+    // Hold a ranged distance from the player
+    Kite,
+}
+
+// How a kind of monster picks its behaviour
+struct Temperament {
+    patrols: bool,
+    // Share of health below which it runs, 0.0 for never
+    flee_below: f32,
+    keep_distance: Option<f32>,
+}
+
+impl Temperament {
+    fn for_monster(kind: MonsterKind) -> Self {
+        match kind {
+            MonsterKind::Goblin => Temperament { patrols: false, flee_below: 0.5, keep_distance: None },
+            MonsterKind::Orc => Temperament { patrols: true, flee_below: 0.2, keep_distance: None },
+            MonsterKind::Skeleton => Temperament { patrols: true, flee_below: 0.0, keep_distance: Some(4.0) },
+            MonsterKind::Ogre => Temperament { patrols: false, flee_below: 0.0, keep_distance: None },
+        }
+    }
+}
+
+#[derive(Component)]
+struct Brain {
+    behaviour: Behaviour,
+    // Waypoints, room centres where there are rooms, visited in turn while patrolling
+    route: Vec<Point>,
+    next_waypoint: usize,
+    path: Vec<Point>,
+}
+
+// How far from its spawn a monster patrols on maps without rooms
+const PATROL_RADIUS: f32 = 10.0;
+
+impl Brain {
+    fn new(route: Vec<Point>) -> Self {
+        Self {
+            behaviour: Behaviour::Wander,
+            route,
+            next_waypoint: 0,
+            path: Vec::new(),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct AiRng(RandomNumberGenerator);
+
+// Seconds an idle monster stands still, on average, between wandering steps
+const WANDER_PAUSE: f32 = 1.5;
+
+fn choose_behaviour(
+    temperament: &Temperament,
+    brain: &Brain,
+    health: &Health,
+    player_near: bool,
+) -> Behaviour {
+    let hurt = (health.current as f32) < temperament.flee_below * health.max as f32;
+    match (player_near, hurt, temperament.keep_distance) {
+        (true, true, _) => Behaviour::Flee,
+        (true, false, Some(_)) => Behaviour::Kite,
+        (true, false, None) => Behaviour::Chase,
+        // A route needs somewhere to go besides the spawn point
+        (false, _, _) if temperament.patrols && brain.route.len() > 1 => Behaviour::Patrol,
+        _ => Behaviour::Wander,
+    }
+}
+
+// The next tile along the route, planning a path to the next waypoint when needed
+fn patrol_step(map: &Map, brain: &mut Brain, position: Point) -> Option<Point> {
+    if brain.path.is_empty() {
+        if brain.route[brain.next_waypoint] == position {
+            brain.next_waypoint = (brain.next_waypoint + 1) % brain.route.len();
+        }
+        let path = a_star_search(
+            map.point2d_to_index(position),
+            map.point2d_to_index(brain.route[brain.next_waypoint]),
+            map,
+        );
+        if !path.success {
+            brain.next_waypoint = (brain.next_waypoint + 1) % brain.route.len();
+            return None;
+        }
+        // The first step is where the monster already stands
+        brain.path = path.steps.iter().skip(1).rev().map(|idx| map.index_to_point2d(*idx)).collect();
+    }
+    brain.path.last().copied()
+}
+
+fn monster_ai(
+    time: Res<Time>,
+    game: Res<Game>,
+    flow_field: Res<FlowField>,
+    mut rng: ResMut<AiRng>,
+    player: Query<&Transform, (With<Player>, Without<Monster>)>,
+    mut monsters: Query<(&Monster, &mut Transform, &mut Movement, &mut Brain, &Stats, &Health)>,
+) {
+    // Tiles taken by a monster standing or stepping there, and by the player
+    let mut occupied: HashSet<Point> = monsters
+        .iter()
+        .flat_map(|(_, transform, movement, ..)| {
+            std::iter::once(world_to_grid(transform.translation)).chain(movement.step)
+        })
+        .collect();
+    occupied.extend(player.iter().map(|transform| world_to_grid(transform.translation)));
+
+    for (monster, mut transform, mut movement, mut brain, stats, health) in &mut monsters {
+        let position = world_to_grid(transform.translation);
+        if movement.step.is_none() {
+            let temperament = Temperament::for_monster(monster.kind);
+            let behaviour = choose_behaviour(
+                &temperament,
+                &brain,
+                health,
+                flow_field.should_chase(&game.map, position),
+            );
+            if behaviour != brain.behaviour {
+                brain.behaviour = behaviour;
+                brain.path.clear();
+            }
+
+            let neighbours: Vec<Point> = game
+                .map
+                .get_available_exits(game.map.point2d_to_index(position))
+                .iter()
+                .map(|(idx, _)| game.map.index_to_point2d(*idx))
+                .filter(|p| !occupied.contains(p))
+                .collect();
+            let chase = |p: &Point| flow_field.distance(&game.map, *p);
+            let flee = |p: &Point| flow_field.flee_distance(&game.map, *p);
+            let here = chase(&position);
+            movement.step = match brain.behaviour {
+                Behaviour::Chase => neighbours
+                    .into_iter()
+                    .filter(|p| chase(p) < here)
+                    .min_by(|a, b| chase(a).total_cmp(&chase(b))),
+                Behaviour::Flee => neighbours
+                    .into_iter()
+                    .filter(|p| flee(p) < flee(&position))
+                    .min_by(|a, b| flee(a).total_cmp(&flee(b))),
+                Behaviour::Kite => {
+                    let keep = temperament.keep_distance.unwrap_or_default();
+                    if here < keep - 1.0 {
+                        neighbours
+                            .into_iter()
+                            .filter(|p| chase(p) > here && chase(p) < f32::MAX)
+                            .max_by(|a, b| chase(a).total_cmp(&chase(b)))
+                    } else if here > keep + 1.0 {
+                        neighbours
+                            .into_iter()
+                            .filter(|p| chase(p) < here)
+                            .min_by(|a, b| chase(a).total_cmp(&chase(b)))
+                    } else {
+                        None
+                    }
+                }
+                Behaviour::Patrol => patrol_step(&game.map, &mut brain, position)
+                    .filter(|p| !occupied.contains(p)),
+                Behaviour::Wander => {
+                    if rng.0.rand::<f32>() < time.delta_seconds() / WANDER_PAUSE {
+                        rng.0.random_slice_entry(&neighbours).copied()
+                    } else {
+                        None
+                    }
+                }
+            };
+            if movement.step.is_some() && brain.behaviour == Behaviour::Patrol {
+                brain.path.pop();
+            }
+            occupied.extend(movement.step);
+        }
+
+        let Some(step) = movement.step else {
+            continue;
+        };
+        let target = grid_to_world(step);
+        let remaining = target - transform.translation;
+        let travel = stats.speed * TILE_SIZE * time.delta_seconds();
+        if remaining.length() <= travel {
+            transform.translation = target;
+            movement.step = None;
+        } else {
+            transform.translation += remaining.normalize() * travel;
+        }
+    }
+}
+
+const MAX_GENERATION_ATTEMPTS: usize = 10;
+
+// Tries fresh seeds until one generates, logging each so it can be replayed with mapgen
+fn generate_level(settings: &GenerationSettings) -> MapBuilder {
+    let mut seeds = RandomNumberGenerator::new();
+    for attempt in 1..=MAX_GENERATION_ATTEMPTS {
+        let seed = seeds.rand::<u64>();
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let architect = ArchitectKind::random(&mut rng);
+        match MapBuilder::with_settings(architect, &mut rng, settings) {
+            Ok(map_builder) => {
+                info!("Generated map from seed {seed}\n{}", map_builder.metrics());
+                return map_builder;
+            }
+            Err(error) => warn!(
+                "Seed {seed} failed to generate ({error}), attempt {attempt} of {MAX_GENERATION_ATTEMPTS}"
+            ),
+        }
+    }
+    // An open floor leaves nothing to go wrong
+    MapBuilder::with_settings(ArchitectKind::Empty, &mut seeds, settings)
+        .expect("the empty architect always generates")
+}
+
+fn spawn_wall(commands: &mut Commands, scene: &Handle<Scene>, map: &Map, position: Point) {
+    let wall = wall_tile(map, position);
+    commands.spawn(SpatialBundle {
+        transform: Transform::from_translation(tile_translation(map, position)),
+        ..default()
+    }).with_children(|parent| {
+        for transform in wall.piece_transforms() {
+            parent.spawn(SceneBundle {
+                transform,
+                scene: scene.clone(),
+                ..default()
+            });
+        }
+    }).insert((MapTile, Wall { position }, PickableBundle::default()));
+}
+
+fn spawn_door(commands: &mut Commands, scene: &Handle<Scene>, map: &Map, position: Point) {
+    let open = map.tiles[map.point2d_to_index(position)] == TileType::OpenDoor;
+    commands.spawn(SceneBundle {
+        transform: Transform::from_translation(tile_translation(map, position)),
+        scene: scene.clone(),
+        ..default()
+    }).insert((MapTile, PickableBundle::default()));
+    commands.spawn(SceneBundle {
+        transform: door_transform(map, position, open),
+        scene: scene.clone(),
+        ..default()
+    }).insert((MapTile, Door { position }));
+}
+
+fn door_transform(map: &Map, position: Point, open: bool) -> Transform {
+    // Closed doors span the corridor between the two walls, open ones swing against a wall
+    let across_x = map
+        .try_idx(position + Point::new(-1, 0))
+        .is_some_and(|idx| map.tiles[idx] == TileType::Wall);
+    let (mut angle, hinge) = if across_x {
+        (0.0, Vec3::new(-0.4, 0.0, 0.0))
+    } else {
+        (std::f32::consts::FRAC_PI_2, Vec3::new(0.0, 0.0, -0.4))
+    };
+    let mut translation = grid_to_world(position) + Vec3::Y * 0.2;
+    if open {
+        angle += std::f32::consts::FRAC_PI_2;
+        translation += hinge;
+    }
+    Transform {
+        translation,
+        rotation: Quat::from_rotation_y(angle),
+        scale: Vec3::new(1.0, 3.0, 0.2),
+    }
+}
+
+fn open_doors(
+    mut game: ResMut<Game>,
+    openers: Query<&Transform, (With<OpensDoors>, Without<Door>)>,
+    mut doors: Query<(&Door, &mut Transform)>,
+) {
+    for opener in &openers {
+        let position = world_to_grid(opener.translation);
+        if game.map.open_door(position) {
+            for (door, mut transform) in &mut doors {
+                if door.position == position {
+                    *transform = door_transform(&game.map, position, true);
+                }
+            }
+        }
+    }
+}
+
+// Hidden doors this many tiles or fewer away are found by searching
+const SEARCH_RADIUS: i32 = 2;
+
+fn search_for_secrets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut game: ResMut<Game>,
+    player: Query<(&ActionState<ArpgAction>, &Transform), With<Player>>,
+    walls: Query<(Entity, &Wall)>,
+) {
+    let Ok((action_state, transform)) = player.get_single() else {
+        return;
+    };
+    if !action_state.just_pressed(ArpgAction::Search) {
+        return;
+    }
+
+    let position = world_to_grid(transform.translation);
+    let mut revealed = Vec::new();
+    for y in -SEARCH_RADIUS..=SEARCH_RADIUS {
+        for x in -SEARCH_RADIUS..=SEARCH_RADIUS {
+            let point = position + Point::new(x, y);
+            if game.map.reveal_hidden_door(point) {
+                revealed.push(point);
+            }
+        }
+    }
+    if revealed.is_empty() {
+        return;
+    }
+
+    // The surrounding walls lose a neighbour, so they are autotiled again
+    let cell_scene = asset_server.load("resources/tile.glb#Scene0");
+    for (entity, wall) in &walls {
+        let affected = revealed.iter().any(|door| {
+            (door.x - wall.position.x).abs() <= 1 && (door.y - wall.position.y).abs() <= 1
+        });
+        if affected {
+            commands.entity(entity).despawn_recursive();
+            if game.map.tiles[game.map.point2d_to_index(wall.position)] != TileType::Door {
+                spawn_wall(&mut commands, &cell_scene, &game.map, wall.position);
+            }
+        }
+    }
+    for door in revealed {
+        spawn_door(&mut commands, &cell_scene, &game.map, door);
+    }
+}
+
+fn pick_up_loot(
+    mut commands: Commands,
+    mut game: ResMut<Game>,
+    mut player: Query<(&Transform, &mut Inventory, &mut Abilities), With<Player>>,
+    pickups: Query<(Entity, &Transform, &Pickup)>,
+) {
+    let Ok((player_transform, mut inventory, mut abilities)) = player.get_single_mut() else {
+        return;
+    };
+    let position = world_to_grid(player_transform.translation);
+    for (entity, transform, pickup) in &pickups {
+        let pickup_position = world_to_grid(transform.translation);
+        if pickup_position != position {
+            continue;
+        }
+        match pickup.kind {
+            LootKind::Gold { amount } => game.score += amount,
+            LootKind::Sword => {
+                // A proper blade swings wide
+                abilities.equip(ArpgAction::Ability2, AbilityKind::Cleave);
+                inventory.items.push(LootKind::Sword);
+            }
+            kind => inventory.items.push(kind),
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn pick_up_amulet(
+    mut state: ResMut<State<GameState>>,
+    mut game: ResMut<Game>,
+    time: Res<Time>,
+    player: Query<&Transform, With<Player>>,
+    amulets: Query<&Transform, With<Amulet>>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    for amulet in &amulets {
+        if world_to_grid(player.translation) == world_to_grid(amulet.translation) {
+            game.run_finished = time.elapsed_seconds_f64();
+            let _ = state.set(GameState::Victory);
+        }
+    }
+}
+
+fn take_exit(
+    mut state: ResMut<State<GameState>>,
+    mut game: ResMut<Game>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let position = world_to_grid(player.translation);
+    if let Some(idx) = game.map.try_idx(position) {
+        if game.map.tiles[idx] == TileType::Exit {
+            game.depth += 1;
+            let _ = state.restart();
+        }
+    }
+}
+
+fn show_victory(mut commands: Commands, asset_server: Res<AssetServer>, game: Res<Game>) {
+    let seconds = (game.run_finished - game.run_started).max(0.0) as u64;
+    commands.spawn(
+        TextBundle::from_section(
+            format!(
+                "You recovered the amulet!\n\nScore: {}\nDepth: {}\nTime: {:02}:{:02}\n\nPress Enter to play again",
+                game.score,
+                game.depth,
+                seconds / 60,
+                seconds % 60,
+            ),
+            TextStyle {
+                font: asset_server.load("resources/FiraMono-Medium.ttf"),
+                font_size: 40.0,
+                color: Color::rgb(1.0, 0.85, 0.3),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(100.0),
+                left: Val::Px(100.0),
+                ..default()
+            },
+            ..default()
+        }),
     );
 }
 
-fn teardown(mut commands: Commands, entities: Query<Entity, Without<Camera>>) {
+fn show_game_over(mut commands: Commands, asset_server: Res<AssetServer>, game: Res<Game>) {
+    commands.spawn(
+        TextBundle::from_section(
+            format!(
+                "You died on depth {}.\n\nScore: {}\n\nPress Enter to play again",
+                game.depth, game.score,
+            ),
+            TextStyle {
+                font: asset_server.load("resources/FiraMono-Medium.ttf"),
+                font_size: 40.0,
+                color: Color::rgb(0.9, 0.2, 0.2),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(100.0),
+                left: Val::Px(100.0),
+                ..default()
+            },
+            ..default()
+        }),
+    );
+}
+
+fn restart_on_enter(
+    keyboard: Res<Input<KeyCode>>,
+    mut state: ResMut<State<GameState>>,
+    mut game: ResMut<Game>,
+) {
+    if keyboard.just_pressed(KeyCode::Return) {
+        game.depth = 0;
+        let _ = state.set(GameState::Playing);
+    }
+}
+
+fn prop_transform(prop: &Prop) -> Transform {
+    // Wall-mounted props hang on the side of the wall facing the floor
+    let mounted = grid_to_world(prop.facing) * 0.55;
+    let position = grid_to_world(prop.position) + Vec3::Y * 0.2;
+    let (offset, scale) = match prop.kind {
+        PropKind::Rubble => (Vec3::ZERO, Vec3::new(0.4, 0.5, 0.4)),
+        PropKind::Bones => (Vec3::ZERO, Vec3::new(0.5, 0.2, 0.15)),
+        PropKind::Torch => (mounted + Vec3::Y * 0.2, Vec3::new(0.1, 1.5, 0.1)),
+        PropKind::Banner => (mounted + Vec3::Y * 0.1, Vec3::new(0.6, 2.0, 0.05)),
+    };
+    Transform {
+        translation: position + offset,
+        rotation: Quat::from_rotation_y(f32::atan2(prop.facing.x as f32, prop.facing.y as f32)),
+        scale,
+    }
+}
+
+fn spawn_snapshot(commands: &mut Commands, scene: &Handle<Scene>, map: &Map) {
+    for idx in 0..map.tiles.len() {
+        commands.spawn(SceneBundle {
+            transform: Transform::from_translation(tile_translation(map, map.index_to_point2d(idx))),
+            scene: scene.clone(),
+            ..default()
+        }).insert(SnapshotTile);
+    }
+}
+
+fn replay_generation(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keyboard: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut replay: ResMut<GenerationReplay>,
+    snapshot_tiles: Query<Entity, With<SnapshotTile>>,
+    mut map_tiles: Query<&mut Visibility, With<MapTile>>,
+) {
+    let toggled = keyboard.just_pressed(KeyCode::F1) && !replay.snapshots.is_empty();
+    let advanced = replay.playing && replay.timer.tick(time.delta()).just_finished();
+    if !toggled && !advanced {
+        return;
+    }
+
+    for entity in &snapshot_tiles {
+        commands.entity(entity).despawn_recursive();
+    }
+    if toggled {
+        replay.playing = !replay.playing;
+        replay.frame = 0;
+        replay.timer.reset();
+    } else {
+        replay.frame += 1;
+    }
+    if replay.frame >= replay.snapshots.len() {
+        replay.playing = false;
+    }
+
+    for mut visibility in &mut map_tiles {
+        visibility.is_visible = !replay.playing;
+    }
+    if replay.playing {
+        let scene = asset_server.load("resources/tile.glb#Scene0");
+        spawn_snapshot(&mut commands, &scene, &replay.snapshots[replay.frame]);
+    }
+}
+
+// The player survives the level it leaves, see `setup`. Only roots are despawned, so the
+// player's own scene children stay with it
+fn teardown(
+    mut commands: Commands,
+    entities: Query<Entity, (Without<Camera>, Without<Parent>)>,
+    player: Query<(), With<Player>>,
+) {
     for entity in &entities {
+        if !player.contains(entity) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// A finished run takes the player with it, so the next one starts from scratch
+fn despawn_player(mut commands: Commands, player: Query<Entity, With<Player>>) {
+    for entity in &player {
         commands.entity(entity).despawn_recursive();
     }
 }
 
 
-fn spawn_player(mut commands: Commands,asset_server: Res<AssetServer>) {
+fn monster_scale(kind: MonsterKind) -> f32 {
+    match kind {
+        MonsterKind::Goblin => 0.6,
+        MonsterKind::Orc => 0.9,
+        MonsterKind::Skeleton => 0.8,
+        MonsterKind::Ogre => 1.4,
+    }
+}
+
+// Despawned with the rest of the level by `teardown`
+fn spawn_monster(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    spawn: &MonsterSpawn,
+    route: Vec<Point>,
+) {
+    let stats = Stats::for_monster(spawn.kind);
+    commands.spawn(SceneBundle {
+        transform: Transform {
+            translation: grid_to_world(spawn.position),
+            rotation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            scale: Vec3::splat(monster_scale(spawn.kind)),
+        },
+        scene: asset_server.load("resources/alien.glb#Scene0"),
+        ..default()
+    }).insert((
+        Monster { kind: spawn.kind },
+        stats,
+        Health { current: stats.max_health, max: stats.max_health },
+        Damage(stats.attack),
+        Defense(stats.defense),
+        AttackCooldown(Timer::from_seconds(ATTACK_INTERVAL, TimerMode::Once)),
+        Movement::default(),
+        Brain::new(route),
+        OpensDoors,
+        PickableBundle::default(),
+    ));
+}
+
+// Called from `setup` at the start of every run
+fn spawn_player(commands: &mut Commands, asset_server: &AssetServer, start: Point) {
     commands.spawn(PlayerBundle {
         player: Player,
         input_manager: InputManagerBundle {
             input_map: PlayerBundle::default_input_map(),
             ..default()
         },
-    }).insert(SceneBundle {
+    }).insert((
+        OpensDoors,
+        Inventory::default(),
+        Health { current: PLAYER_HEALTH, max: PLAYER_HEALTH },
+        Damage(3),
+        Defense(1),
+        Abilities::default_loadout(),
+        Mana { current: PLAYER_MANA, max: PLAYER_MANA },
+        Facing(screen_to_world(Vec2::Y)),
+    )).insert(SceneBundle {
         transform: Transform {
-            translation: Vec3::new(5 as f32, 0., 5 as f32),
+            translation: grid_to_world(start),
             rotation: Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2),
             ..default()
         },
@@ -289,18 +1334,361 @@ fn spawn_player(mut commands: Commands,asset_server: Res<AssetServer>) {
     });
 }
 
-fn cast_fireball(query: Query<&ActionState<ArpgAction>, With<Player>>) {
-    let action_state = query.single();
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum AbilityKind {
+    Fireball,
+    Strike,
+    Cleave,
+    Heal,
+    Dash,
+    Nova,
+}
 
-    if action_state.just_pressed(ArpgAction::Ability1) {
-        println!("Fwoosh!");
+#[derive(Clone, Copy, Debug)]
+enum AbilityEffect {
+    // Handled by `cast_fireball`, `speed` and `range` in tiles
+    Fireball { damage: i32, radius: f32, speed: f32, range: f32 },
+    // Handled by `player_dash`, `distance` in tiles and `invulnerable` in seconds
+    Dash { distance: f32, invulnerable: f32 },
+    // Hits every monster within `reach` tiles for the caster's damage plus `bonus`
+    Melee { bonus: i32, reach: f32 },
+    Heal { amount: i32 },
+    Nova { damage: i32, radius: f32 },
+}
+
+struct AbilityDef {
+    name: &'static str,
+    // Seconds
+    cooldown: f32,
+    mana_cost: f32,
+    cast_time: f32,
+    effect: AbilityEffect,
+}
+
+impl AbilityKind {
+    fn def(self) -> AbilityDef {
+        use AbilityEffect::*;
+        match self {
+            AbilityKind::Fireball => AbilityDef {
+                name: "Fireball",
+                cooldown: 1.0,
+                mana_cost: 15.0,
+                cast_time: 0.3,
+                effect: Fireball { damage: 8, radius: 1.5, speed: 10.0, range: 10.0 },
+            },
+            AbilityKind::Strike => AbilityDef {
+                name: "Strike",
+                cooldown: 0.5,
+                mana_cost: 0.0,
+                cast_time: 0.1,
+                effect: Melee { bonus: 0, reach: 1.2 },
+            },
+            AbilityKind::Cleave => AbilityDef {
+                name: "Cleave",
+                cooldown: 1.5,
+                mana_cost: 10.0,
+                cast_time: 0.2,
+                effect: Melee { bonus: 4, reach: 1.6 },
+            },
+            AbilityKind::Heal => AbilityDef {
+                name: "Heal",
+                cooldown: 8.0,
+                mana_cost: 20.0,
+                cast_time: 0.5,
+                effect: Heal { amount: 10 },
+            },
+            AbilityKind::Dash => AbilityDef {
+                name: "Dash",
+                cooldown: 2.0,
+                mana_cost: 5.0,
+                cast_time: 0.0,
+                effect: Dash { distance: 3.0, invulnerable: 0.4 },
+            },
+            AbilityKind::Nova => AbilityDef {
+                name: "Nova",
+                cooldown: 30.0,
+                mana_cost: 50.0,
+                cast_time: 1.0,
+                effect: Nova { damage: 12, radius: 4.0 },
+            },
+        }
     }
 }
 
-fn player_dash(query: Query<&ActionState<ArpgAction>, With<Player>>) {
-    let action_state = query.single();
+struct AbilitySlot {
+    action: ArpgAction,
+    ability: Option<AbilityKind>,
+    // Seconds until the slot can be used again
+    cooldown: f32,
+}
+
+#[derive(Component)]
+struct Abilities {
+    slots: Vec<AbilitySlot>,
+    // The ability being cast and the seconds left until it goes off
+    casting: Option<(AbilityKind, f32)>,
+}
+
+impl Abilities {
+    fn default_loadout() -> Self {
+        let slot = |action, ability| AbilitySlot { action, ability: Some(ability), cooldown: 0.0 };
+        Self {
+            slots: vec![
+                slot(ArpgAction::Ability1, AbilityKind::Fireball),
+                slot(ArpgAction::Ability2, AbilityKind::Strike),
+                slot(ArpgAction::Ability3, AbilityKind::Heal),
+                slot(ArpgAction::Ability4, AbilityKind::Dash),
+                slot(ArpgAction::Ultimate, AbilityKind::Nova),
+            ],
+            casting: None,
+        }
+    }
+
+    /// Binds `ability` to the key of `action`, starting it off cooled down.
+    fn equip(&mut self, action: ArpgAction, ability: AbilityKind) {
+        if let Some(slot) = self.slots.iter_mut().find(|s| s.action == action) {
+            slot.ability = Some(ability);
+            slot.cooldown = 0.0;
+        }
+    }
+}
+
+#[derive(Component)]
+struct Mana {
+    current: f32,
+    max: f32,
+}
+
+const PLAYER_MANA: f32 = 100.0;
+// Per second
+const MANA_REGEN: f32 = 4.0;
+
+// Sent when an ability finishes casting, for its effect to be applied
+struct AbilityEvent {
+    caster: Entity,
+    ability: AbilityKind,
+}
 
-    if action_state.just_pressed(ArpgAction::Ability4) {
+fn use_abilities(
+    time: Res<Time>,
+    mut casters: Query<(Entity, &ActionState<ArpgAction>, &mut Abilities, &mut Mana)>,
+    mut ability_events: EventWriter<AbilityEvent>,
+) {
+    let dt = time.delta_seconds();
+    for (caster, action_state, mut abilities, mut mana) in &mut casters {
+        mana.current = (mana.current + MANA_REGEN * dt).min(mana.max);
+        for slot in abilities.slots.iter_mut() {
+            slot.cooldown = (slot.cooldown - dt).max(0.0);
+        }
+
+        if let Some((ability, remaining)) = abilities.casting {
+            abilities.casting = if remaining <= dt {
+                ability_events.send(AbilityEvent { caster, ability });
+                None
+            } else {
+                Some((ability, remaining - dt))
+            };
+            continue;
+        }
+
+        let Some(slot) = abilities.slots.iter_mut().find(|s| action_state.just_pressed(s.action))
+        else {
+            continue;
+        };
+        let Some(ability) = slot.ability else {
+            continue;
+        };
+        let def = ability.def();
+        if slot.cooldown > 0.0 || mana.current < def.mana_cost {
+            info!("{} is not ready", def.name);
+            continue;
+        }
+        mana.current -= def.mana_cost;
+        slot.cooldown = def.cooldown;
+        if def.cast_time > 0.0 {
+            abilities.casting = Some((ability, def.cast_time));
+        } else {
+            ability_events.send(AbilityEvent { caster, ability });
+        }
+    }
+}
+
+fn apply_ability_effects(
+    mut ability_events: EventReader<AbilityEvent>,
+    mut casters: Query<(&Transform, &Damage, &mut Health)>,
+    monsters: Query<(Entity, &Transform), With<Monster>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for event in ability_events.iter() {
+        let Ok((caster, damage, mut health)) = casters.get_mut(event.caster) else {
+            continue;
+        };
+        let (amount, radius) = match event.ability.def().effect {
+            AbilityEffect::Melee { bonus, reach } => (damage.0 + bonus, reach),
+            AbilityEffect::Nova { damage, radius } => (damage, radius),
+            AbilityEffect::Heal { amount } => {
+                health.current = (health.current + amount).min(health.max);
+                continue;
+            }
+            AbilityEffect::Fireball { .. } | AbilityEffect::Dash { .. } => continue,
+        };
+        for (target, transform) in &monsters {
+            if transform.translation.distance(caster.translation) <= radius * TILE_SIZE {
+                damage_events.send(DamageEvent { target, amount });
+            }
+        }
+    }
+}
+
+// The way the player last walked, along the floor
+#[derive(Component)]
+struct Facing(Vec3);
+
+#[derive(Component)]
+struct Projectile {
+    direction: Vec3,
+    // Tiles per second
+    speed: f32,
+    // Tiles left before it bursts on its own
+    range: f32,
+    damage: i32,
+    radius: f32,
+}
+
+// A short flash, such as a burst projectile, gone when the timer runs out
+#[derive(Component)]
+struct Explosion(Timer);
+
+// How close, in tiles, a projectile passes a monster before it bursts on it
+const PROJECTILE_HIT_RADIUS: f32 = 0.5;
+
+fn cast_fireball(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut ability_events: EventReader<AbilityEvent>,
+    casters: Query<(&Transform, &Facing)>,
+) {
+    for event in ability_events.iter() {
+        let AbilityEffect::Fireball { damage, radius, speed, range } = event.ability.def().effect
+        else {
+            continue;
+        };
+        let Ok((transform, facing)) = casters.get(event.caster) else {
+            continue;
+        };
+        commands.spawn(SceneBundle {
+            transform: Transform::from_translation(transform.translation + Vec3::Y * 0.5)
+                .with_scale(Vec3::splat(0.3)),
+            scene: asset_server.load("resources/tile.glb#Scene0"),
+            ..default()
+        }).insert(Projectile { direction: facing.0, speed, range, damage, radius });
+    }
+}
+
+fn move_projectiles(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    game: Res<Game>,
+    mut projectiles: Query<(Entity, &mut Transform, &mut Projectile), Without<Monster>>,
+    monsters: Query<(Entity, &Transform), With<Monster>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, mut transform, mut projectile) in &mut projectiles {
+        let travel = (projectile.speed * time.delta_seconds()).min(projectile.range);
+        projectile.range -= travel;
+
+        // March the segment in steps shorter than the hit radius, so a fast projectile
+        // can't pass through a thin wall or slip past a monster between frames
+        let steps = (travel / PROJECTILE_HIT_RADIUS).ceil().max(1.0);
+        let step = projectile.direction * travel / steps * TILE_SIZE;
+        let mut hit_wall = false;
+        let mut hit_monster = false;
+        for _ in 0..steps as usize {
+            let next = transform.translation + step;
+            // Anything that blocks sight, closed doors included, stops the projectile
+            hit_wall = game
+                .map
+                .try_idx(world_to_grid(next))
+                .is_none_or(|idx| game.map.is_opaque(idx));
+            if hit_wall {
+                break;
+            }
+            transform.translation = next;
+            hit_monster = monsters.iter().any(|(_, monster)| {
+                floor_distance(monster.translation, next) <= PROJECTILE_HIT_RADIUS * TILE_SIZE
+            });
+            if hit_monster {
+                break;
+            }
+        }
+        if !(hit_wall || hit_monster || projectile.range <= 0.0) {
+            continue;
+        }
+
+        // Burst where it got to, catching everything around it
+        for (target, monster) in &monsters {
+            if floor_distance(monster.translation, transform.translation)
+                <= projectile.radius * TILE_SIZE
+            {
+                damage_events.send(DamageEvent { target, amount: projectile.damage });
+            }
+        }
+        commands.entity(entity).despawn_recursive();
+        commands.spawn(SceneBundle {
+            transform: Transform::from_translation(transform.translation)
+                .with_scale(Vec3::splat(projectile.radius * 2.0 * TILE_SIZE)),
+            scene: asset_server.load("resources/tile.glb#Scene0"),
+            ..default()
+        }).insert(Explosion(Timer::from_seconds(0.2, TimerMode::Once)));
+    }
+}
+
+fn fade_explosions(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut explosions: Query<(Entity, &mut Explosion)>,
+) {
+    for (entity, mut explosion) in &mut explosions {
+        if explosion.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// Taking no damage until the timer runs out
+#[derive(Component)]
+struct Invulnerable(Timer);
+
+#[derive(Component)]
+struct Dashing {
+    direction: Direction,
+    // Tiles still to cover
+    remaining: f32,
+}
+
+// Sent when a dash starts, for effects and sounds to hook into
+struct DashEvent {
+    from: Vec3,
+    direction: Direction,
+}
+
+// Tiles per second
+const DASH_SPEED: f32 = 20.0;
+
+fn player_dash(
+    mut commands: Commands,
+    mut ability_events: EventReader<AbilityEvent>,
+    mut dash_events: EventWriter<DashEvent>,
+    query: Query<(&ActionState<ArpgAction>, &Transform, &Facing), With<Player>>,
+) {
+    for event in ability_events.iter() {
+        let AbilityEffect::Dash { distance, invulnerable } = event.ability.def().effect else {
+            continue;
+        };
+        let Ok((action_state, transform, facing)) = query.get(event.caster) else {
+            continue;
+        };
         let mut direction_vector = Vec2::ZERO;
 
         for input_direction in ArpgAction::DIRECTIONS {
@@ -312,12 +1700,82 @@ fn player_dash(query: Query<&ActionState<ArpgAction>, With<Player>>) {
             }
         }
 
-        // Then reconvert at the end, normalizing the magnitude
+        // Then reconvert at the end, normalizing the magnitude. Without input the dash
+        // goes the way the player faces
         let net_direction: Result<Direction, NearlySingularConversion> =
             direction_vector.try_into();
+        let direction = net_direction
+            .unwrap_or_else(|_| Direction::new(world_to_screen(facing.0)));
+
+        commands.entity(event.caster).insert((
+            Dashing { direction, remaining: distance },
+            Invulnerable(Timer::from_seconds(invulnerable, TimerMode::Once)),
+        ));
+        dash_events.send(DashEvent { from: transform.translation, direction });
+    }
+}
+
+fn dash_movement(
+    mut commands: Commands,
+    time: Res<Time>,
+    game: Res<Game>,
+    mut dashers: Query<(Entity, &mut Transform, &mut Dashing)>,
+    mut walk_events: EventWriter<PlayerWalk>,
+) {
+    for (entity, mut transform, mut dashing) in &mut dashers {
+        let travel = (DASH_SPEED * time.delta_seconds()).min(dashing.remaining);
+        dashing.remaining -= travel;
+
+        // Step no further than the footprint's half width at a time, so a fast dash can't
+        // skip over a thin wall, and stop at the last position the player fits
+        let steps = (travel / PLAYER_RADIUS).ceil().max(1.0);
+        let step = screen_to_world(dashing.direction.unit_vector()) * travel / steps * TILE_SIZE;
+        let mut blocked = false;
+        for _ in 0..steps as usize {
+            let next = transform.translation + step;
+            // The first wall or closed door in the way ends the dash
+            blocked = !can_stand_at(&game.map, next.x, next.z)
+                || footprint(next.x, next.z)
+                    .iter()
+                    .any(|p| game.map.tiles[game.map.point2d_to_index(*p)] == TileType::Door);
+            if blocked {
+                break;
+            }
+            transform.translation = next;
+        }
+        if blocked || dashing.remaining <= 0.0 {
+            commands.entity(entity).remove::<Dashing>();
+            walk_events.send(PlayerWalk { direction: dashing.direction });
+        }
+    }
+}
+
+// A brief streak where the dash started, pointing the way it went
+fn show_dash_trail(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut dash_events: EventReader<DashEvent>,
+) {
+    for event in dash_events.iter() {
+        let direction = screen_to_world(event.direction.unit_vector());
+        commands.spawn(SceneBundle {
+            transform: Transform::from_translation(event.from)
+                .looking_at(event.from + direction, Vec3::Y)
+                .with_scale(Vec3::new(0.2, 0.05, 1.5) * TILE_SIZE),
+            scene: asset_server.load("resources/tile.glb#Scene0"),
+            ..default()
+        }).insert(Explosion(Timer::from_seconds(0.15, TimerMode::Once)));
+    }
+}
 
-        if let Ok(direction) = net_direction {
-            println!("Dashing in {direction:?}");
+fn expire_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut entities: Query<(Entity, &mut Invulnerable)>,
+) {
+    for (entity, mut invulnerable) in &mut entities {
+        if invulnerable.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Invulnerable>();
         }
     }
 }
@@ -326,13 +1784,41 @@ pub struct PlayerWalk {
     pub direction: Direction,
 }
 
+// Tiles per second
+const PLAYER_SPEED: f32 = 4.0;
+// Half the width of the player's footprint, in tiles
+const PLAYER_RADIUS: f32 = 0.3;
+
+// The tiles under the corners of the player's footprint when centred at `x`, `z`
+fn footprint(x: f32, z: f32) -> [Point; 4] {
+    [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(dx, dz)| {
+        world_to_grid(Vec3::new(
+            x + dx * PLAYER_RADIUS * TILE_SIZE,
+            0.0,
+            z + dz * PLAYER_RADIUS * TILE_SIZE,
+        ))
+    })
+}
+
+// Whether the player's whole footprint fits on enterable tiles when centred at `x`, `z`
+fn can_stand_at(map: &Map, x: f32, z: f32) -> bool {
+    footprint(x, z).iter().all(|p| map.can_enter_tile(*p))
+}
+
 fn player_walks(
-    query: Query<&ActionState<ArpgAction>, With<Player>>,
+    time: Res<Time>,
+    game: Res<Game>,
     mut event_writer: EventWriter<PlayerWalk>,
-    mut player_query: Query<&mut Transform, With<Player>>
+    mut player_query: Query<(&ActionState<ArpgAction>, &mut Transform, &mut Facing), With<Player>>,
+    dashing: Query<(), (With<Player>, With<Dashing>)>,
 ) {
-    let action_state = query.single();
-    let mut player = player_query.single_mut();
+    // The dash carries the player until it ends
+    if !dashing.is_empty() {
+        return;
+    }
+    let Ok((action_state, mut player, mut facing)) = player_query.get_single_mut() else {
+        return;
+    };
 
     let mut direction_vector = Vec2::ZERO;
 
@@ -349,8 +1835,20 @@ fn player_walks(
     let net_direction: Result<Direction, NearlySingularConversion> = direction_vector.try_into();
 
     if let Ok(direction) = net_direction {
-        player.translation += Vec3::new(direction.unit_vector().y, 0.0, direction.unit_vector().x);
-        println!("Player walks x:{} y:{}", direction.unit_vector().x, direction.unit_vector().y);
+        facing.0 = screen_to_world(direction.unit_vector());
+        let step = facing.0
+            * PLAYER_SPEED
+            * TILE_SIZE
+            * time.delta_seconds();
+        // Each axis is resolved on its own, so diagonal input slides along walls
+        let mut translation = player.translation;
+        if can_stand_at(&game.map, translation.x + step.x, translation.z) {
+            translation.x += step.x;
+        }
+        if can_stand_at(&game.map, translation.x, translation.z + step.z) {
+            translation.z += step.z;
+        }
+        player.translation = translation;
         event_writer.send(PlayerWalk { direction });
     }
 }
\ No newline at end of file
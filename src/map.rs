@@ -1,48 +1,98 @@
 
 use crate::prelude::*;
 
-const NUM_TILES: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
-
 #[derive(Copy, Clone, PartialEq)]
 pub enum TileType {
     Wall,
     Floor,
     Exit,
+    Door,
+    OpenDoor,
+    // Looks and blocks like a wall until searched for
+    HiddenDoor,
 }
 
-pub fn map_idx(x: i32, y: i32) -> usize {
-    ((y * SCREEN_WIDTH) + x) as usize
-}
+// The four neighbours a step can reach, in the order pathfinding tries them
+pub const CARDINALS: [Point; 4] = [
+    Point { x: -1, y: 0 },
+    Point { x: 1, y: 0 },
+    Point { x: 0, y: -1 },
+    Point { x: 0, y: 1 },
+];
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Map {
+    pub width: i32,
+    pub height: i32,
     pub tiles: Vec<TileType>,
     pub revealed_tiles: Vec<bool>,
 }
 
 impl Map {
     pub fn new() -> Self {
+        Self::with_size(SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    pub fn with_size(width: i32, height: i32) -> Self {
+        let num_tiles = (width * height) as usize;
         Self {
-            tiles: vec![TileType::Floor; NUM_TILES],
-            revealed_tiles: vec![false;NUM_TILES],
+            width,
+            height,
+            tiles: vec![TileType::Floor; num_tiles],
+            revealed_tiles: vec![false; num_tiles],
         }
     }
 
+    pub fn idx(&self, x: i32, y: i32) -> usize {
+        ((y * self.width) + x) as usize
+    }
+
     pub fn in_bounds(&self, point: Point) -> bool {
-        point.x >= 0 && point.x < SCREEN_WIDTH && point.y >= 0 && point.y < SCREEN_HEIGHT
+        point.x >= 0 && point.x < self.width && point.y >= 0 && point.y < self.height
+    }
+
+    // Inside the map and off its outer edge, which always stays wall
+    pub fn is_interior(&self, point: Point) -> bool {
+        point.x > 0 && point.x < self.width - 1 && point.y > 0 && point.y < self.height - 1
     }
 
     pub fn try_idx(&self, point: Point) -> Option<usize> {
         if !self.in_bounds(point) {
             None
         } else {
-            Some(map_idx(point.x, point.y))
+            Some(self.idx(point.x, point.y))
         }
     }
 
+    // Closed doors can be entered, entering one is what opens it
     pub fn can_enter_tile(&self, point: Point) -> bool {
-        self.in_bounds(point) && self.tiles[map_idx(point.x, point.y)] == TileType::Floor ||
-        self.tiles[map_idx(point.x, point.y)] == TileType::Exit
+        self.in_bounds(point)
+            && matches!(
+                self.tiles[self.idx(point.x, point.y)],
+                TileType::Floor | TileType::Exit | TileType::Door | TileType::OpenDoor
+            )
+    }
+
+    /// Opens the door at `point`, returning false if there was no closed door.
+    pub fn open_door(&mut self, point: Point) -> bool {
+        match self.try_idx(point) {
+            Some(idx) if self.tiles[idx] == TileType::Door => {
+                self.tiles[idx] = TileType::OpenDoor;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Turns the hidden door at `point` into a closed door, returning false if there was none.
+    pub fn reveal_hidden_door(&mut self, point: Point) -> bool {
+        match self.try_idx(point) {
+            Some(idx) if self.tiles[idx] == TileType::HiddenDoor => {
+                self.tiles[idx] = TileType::Door;
+                true
+            }
+            _ => false,
+        }
     }
 
     fn valid_exit(&self, loc: Point, delta: Point) -> Option<usize> {
@@ -65,17 +115,10 @@ impl BaseMap for Map {
         let mut exits = SmallVec::new();
         let location = self.index_to_point2d(idx);
 
-        if let Some(idx) = self.valid_exit(location, Point::new(-1, 0)) {
-            exits.push((idx, 1.0))
-        }
-        if let Some(idx) = self.valid_exit(location, Point::new(1, 0)) {
-            exits.push((idx, 1.0))
-        }
-        if let Some(idx) = self.valid_exit(location, Point::new(0, -1)) {
-            exits.push((idx, 1.0))
-        }
-        if let Some(idx) = self.valid_exit(location, Point::new(0, 1)) {
-            exits.push((idx, 1.0))
+        for delta in CARDINALS {
+            if let Some(idx) = self.valid_exit(location, delta) {
+                exits.push((idx, 1.0))
+            }
         }
 
         exits
@@ -87,15 +130,56 @@ impl BaseMap for Map {
     }
 
     fn is_opaque(&self, _idx: usize) -> bool {
-        self.tiles[_idx as usize] != TileType::Floor
+        !matches!(self.tiles[_idx as usize], TileType::Floor | TileType::Exit | TileType::OpenDoor)
     }
 }
 impl Algorithm2D for Map {
     fn dimensions(&self) -> Point {
-        Point::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+        Point::new(self.width, self.height)
     }
 
     fn in_bounds(&self, point: Point) -> bool {
         self.in_bounds(point)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_closed_doors_open() {
+        let mut map = Map::with_size(10, 10);
+        let door = Point::new(3, 4);
+        let idx = map.idx(3, 4);
+        map.tiles[idx] = TileType::Door;
+        assert!(map.open_door(door));
+        assert!(map.tiles[idx] == TileType::OpenDoor);
+        assert!(!map.open_door(door));
+        assert!(!map.open_door(Point::new(4, 4)));
+        assert!(!map.open_door(Point::new(-1, 4)));
+    }
+
+    #[test]
+    fn revealed_hidden_doors_become_closed_doors() {
+        let mut map = Map::with_size(10, 10);
+        let door = Point::new(3, 4);
+        let idx = map.idx(3, 4);
+        map.tiles[idx] = TileType::HiddenDoor;
+        assert!(!map.can_enter_tile(door));
+        assert!(map.reveal_hidden_door(door));
+        assert!(map.tiles[idx] == TileType::Door);
+        assert!(map.can_enter_tile(door));
+        assert!(!map.reveal_hidden_door(door));
+        assert!(!map.reveal_hidden_door(Point::new(10, 4)));
+    }
+
+    #[test]
+    fn nothing_off_the_map_can_be_entered() {
+        let map = Map::with_size(10, 10);
+        assert!(map.can_enter_tile(Point::new(0, 9)));
+        for p in [Point::new(-1, 0), Point::new(0, -1), Point::new(10, 0), Point::new(0, 10)] {
+            assert!(!map.can_enter_tile(p));
+        }
+    }
+}
@@ -3,25 +3,38 @@ use super::MapArchitect;
 
 pub struct CellularAutomataArchitect {}
 
+const MONSTER_DENSITY: f32 = 0.03;
+
 impl MapArchitect for CellularAutomataArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
+    fn new(
+        &mut self,
+        rng: &mut RandomNumberGenerator,
+        settings: &GenerationSettings,
+    ) -> Result<MapBuilder, MapGenError> {
         let mut mb = MapBuilder{
-            map : Map::new(),
+            map : Map::with_size(settings.width, settings.height),
             rooms: Vec::new(),
+            secret_rooms: Vec::new(),
+            depth : settings.depth,
             monster_spawns : Vec::new(),
+            loot : Vec::new(),
             player_start : Point::zero(),
             amulet_start : Point::zero(),
+            props : Vec::new(),
+            snapshots : settings.record_snapshots.then(Vec::new),
             // theme: super::themes::DungeonTheme::new()
         };
         self.random_noise_map(rng, &mut mb.map);
+        mb.take_snapshot();
         for _ in 0..10 {
             self.iteration(&mut mb.map);
+            mb.take_snapshot();
         }
-        let start = self.find_start(&mb.map);
-        mb.monster_spawns = mb.spawn_monsters(&start, rng);
+        let start = self.find_start(&mb.map)?;
         mb.player_start = start;
-        mb.amulet_start = mb.find_most_distant();
-        mb
+        mb.amulet_start = mb.find_most_distant()?;
+        mb.spawn_monsters(settings.monster_density.unwrap_or(MONSTER_DENSITY), rng);
+        Ok(mb)
     }
 }
 
@@ -46,7 +59,7 @@ impl CellularAutomataArchitect {
         for iy in -1 ..= 1 {
             for ix in -1 ..= 1 {
                 if !(ix==0 && iy == 0) &&// (5)
-                    map.tiles[map_idx(x+ix, y+iy)] == TileType::Wall
+                    map.tiles[map.idx(x+ix, y+iy)] == TileType::Wall
                 {
                     neighbors += 1;
                 }
@@ -58,10 +71,10 @@ impl CellularAutomataArchitect {
 
     fn iteration(&mut self, map: &mut Map) {
         let mut new_tiles = map.tiles.clone();// (6)
-        for y in 1 .. SCREEN_HEIGHT -1 {// (7)
-            for x in 1 .. SCREEN_WIDTH -1 {
+        for y in 1 .. map.height -1 {// (7)
+            for x in 1 .. map.width -1 {
                 let neighbors = self.count_neighbors(x, y, map);// (8)
-                let idx = map_idx(x, y);
+                let idx = map.idx(x, y);
                 if neighbors > 4 || neighbors == 0 {// (9)
                     new_tiles[idx] = TileType::Wall;
                 } else {
@@ -72,8 +85,8 @@ impl CellularAutomataArchitect {
         map.tiles = new_tiles;
     }
 
-    fn find_start(&self, map: &Map) -> Point {
-        let center = Point::new(SCREEN_WIDTH/2, SCREEN_HEIGHT/2);// (10)
+    fn find_start(&self, map: &Map) -> Result<Point, MapGenError> {
+        let center = Point::new(map.width/2, map.height/2);// (10)
         let closest_point = map.tiles
             .iter()// (11)
             .enumerate()// (12)
@@ -82,11 +95,22 @@ impl CellularAutomataArchitect {
                 center,
                 map.index_to_point2d(idx)
             )))
-            .min_by(|(_, distance), (_, distance2)| 
-                distance.partial_cmp(&distance2).unwrap()// (15)
+            .min_by(|(_, distance), (_, distance2)|
+                distance.total_cmp(distance2)// (15)
             )
             .map(|(idx, _)| idx)// (16)
-            .unwrap();// (17)
-        map.index_to_point2d(closest_point)// (18)
+            .ok_or(MapGenError::NoFloor)?;// (17)
+        Ok(map.index_to_point2d(closest_point))// (18)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solid_rock_has_no_start() {
+        let mut map = Map::with_size(20, 20);
+        map.tiles.iter_mut().for_each(|t| *t = TileType::Wall);
+        assert_eq!(CellularAutomataArchitect {}.find_start(&map), Err(MapGenError::NoFloor));
+    }
+}
@@ -0,0 +1,395 @@
+use crate::prelude::*;
+
+/// Which pairs of rooms get connected.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CorridorLayout {
+    // Rooms sorted by centre x, each joined to the previous one
+    SortedByX,
+    MinimumSpanningTree { extra_loops: usize },
+    // A chain that always continues to the closest unvisited room
+    NearestNeighbour,
+}
+
+/// How the tunnel between two connected rooms is dug.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CorridorRouting {
+    LShaped,
+    // Shortest path that goes around the other rooms
+    AStar,
+    Drunken,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CorridorStyle {
+    pub layout: CorridorLayout,
+    pub routing: CorridorRouting,
+    pub width: i32,
+}
+
+impl Default for CorridorStyle {
+    fn default() -> Self {
+        Self {
+            layout: CorridorLayout::SortedByX,
+            routing: CorridorRouting::LShaped,
+            width: 1,
+        }
+    }
+}
+
+impl CorridorStyle {
+    pub fn random(rng: &mut RandomNumberGenerator) -> Self {
+        let layout = match rng.range(0, 3) {
+            0 => CorridorLayout::SortedByX,
+            1 => CorridorLayout::MinimumSpanningTree {
+                extra_loops: rng.range(0, 4) as usize,
+            },
+            _ => CorridorLayout::NearestNeighbour,
+        };
+        let routing = match rng.range(0, 3) {
+            0 => CorridorRouting::LShaped,
+            1 => CorridorRouting::AStar,
+            _ => CorridorRouting::Drunken,
+        };
+        Self {
+            layout,
+            routing,
+            width: if rng.range(0, 4) == 0 { 2 } else { 1 },
+        }
+    }
+}
+
+// Walkable grid used to route corridors around rooms that are not being connected
+struct CorridorPlanner {
+    dimensions: Point,
+    blocked: Vec<bool>,
+}
+
+impl BaseMap for CorridorPlanner {
+    fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        let mut exits = SmallVec::new();
+        let location = self.index_to_point2d(idx);
+        for delta in [
+            Point::new(-1, 0),
+            Point::new(1, 0),
+            Point::new(0, -1),
+            Point::new(0, 1),
+        ] {
+            let destination = location + delta;
+            if destination.x > 0
+                && destination.x < self.dimensions.x - 1
+                && destination.y > 0
+                && destination.y < self.dimensions.y - 1
+            {
+                let idx = self.point2d_to_index(destination);
+                if !self.blocked[idx] {
+                    exits.push((idx, 1.0));
+                }
+            }
+        }
+        exits
+    }
+
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        DistanceAlg::Manhattan.distance2d(self.index_to_point2d(idx1), self.index_to_point2d(idx2))
+    }
+}
+
+impl Algorithm2D for CorridorPlanner {
+    fn dimensions(&self) -> Point {
+        self.dimensions
+    }
+}
+
+impl MapBuilder {
+    pub(super) fn build_corridors(&mut self, style: CorridorStyle, rng: &mut RandomNumberGenerator) {
+        for (from, to) in self.corridor_edges(style.layout, rng) {
+            match style.routing {
+                CorridorRouting::LShaped => self.apply_l_tunnel(from, to, style.width, rng),
+                CorridorRouting::AStar => self.apply_routed_tunnel(from, to, style.width, rng),
+                CorridorRouting::Drunken => self.apply_drunken_tunnel(from, to, style.width, rng),
+            }
+            self.take_snapshot();
+        }
+    }
+
+    fn corridor_edges(
+        &self,
+        layout: CorridorLayout,
+        rng: &mut RandomNumberGenerator,
+    ) -> Vec<(usize, usize)> {
+        match layout {
+            CorridorLayout::SortedByX => {
+                let mut order: Vec<usize> = (0..self.rooms.len()).collect();
+                order.sort_by_key(|i| self.rooms[*i].center().x);
+                order.windows(2).map(|pair| (pair[0], pair[1])).collect()
+            }
+            CorridorLayout::MinimumSpanningTree { extra_loops } => {
+                self.spanning_tree_edges(extra_loops, rng)
+            }
+            CorridorLayout::NearestNeighbour => {
+                let mut edges = Vec::new();
+                let mut visited = vec![false; self.rooms.len()];
+                let mut current = 0;
+                visited[current] = true;
+                while let Some(next) = (0..self.rooms.len())
+                    .filter(|i| !visited[*i])
+                    .min_by_key(|i| self.room_distance(current, *i))
+                {
+                    edges.push((current, next));
+                    visited[next] = true;
+                    current = next;
+                }
+                edges
+            }
+        }
+    }
+
+    fn room_distance(&self, a: usize, b: usize) -> i32 {
+        let (a, b) = (self.rooms[a].center(), self.rooms[b].center());
+        (a.x - b.x).pow(2) + (a.y - b.y).pow(2)
+    }
+
+    // Kruskal's algorithm, followed by a few of the shorter unused edges to create loops
+    fn spanning_tree_edges(
+        &self,
+        extra_loops: usize,
+        rng: &mut RandomNumberGenerator,
+    ) -> Vec<(usize, usize)> {
+        let count = self.rooms.len();
+        let mut candidates: Vec<(usize, usize)> = (0..count)
+            .flat_map(|a| (a + 1..count).map(move |b| (a, b)))
+            .collect();
+        candidates.sort_by_key(|(a, b)| self.room_distance(*a, *b));
+
+        let mut parent: Vec<usize> = (0..count).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+
+        let mut edges = Vec::new();
+        let mut unused = Vec::new();
+        for (a, b) in candidates {
+            let (root_a, root_b) = (root(&mut parent, a), root(&mut parent, b));
+            if root_a == root_b {
+                unused.push((a, b));
+            } else {
+                parent[root_a] = root_b;
+                edges.push((a, b));
+            }
+        }
+
+        // Only consider the shortest third so loops stay local
+        unused.truncate(unused.len() / 3);
+        for _ in 0..extra_loops {
+            if let Some(i) = rng.random_slice_index(&unused) {
+                edges.push(unused.remove(i));
+            }
+        }
+        edges
+    }
+
+    fn carve(&mut self, point: Point, width: i32) {
+        for dy in 0..width {
+            for dx in 0..width {
+                let p = Point::new(point.x + dx - width / 2, point.y + dy - width / 2);
+                if self.map.is_interior(p) {
+                    let idx = self.map.point2d_to_index(p);
+                    self.map.tiles[idx] = TileType::Floor;
+                }
+            }
+        }
+    }
+
+    fn apply_l_tunnel(&mut self, from: usize, to: usize, width: i32, rng: &mut RandomNumberGenerator) {
+        let prev = self.rooms[from].center();
+        let new = self.rooms[to].center();
+
+        if rng.range(0, 2) == 1 {
+            self.apply_horizontal_tunnel(prev.x, new.x, prev.y, width);
+            self.apply_vertical_tunnel(prev.y, new.y, new.x, width);
+        } else {
+            self.apply_vertical_tunnel(prev.y, new.y, prev.x, width);
+            self.apply_horizontal_tunnel(prev.x, new.x, new.y, width);
+        }
+    }
+
+    fn apply_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32, width: i32) {
+        use std::cmp::{max, min};
+        for x in min(x1, x2)..=max(x1, x2) {
+            self.carve(Point::new(x, y), width);
+        }
+    }
+
+    fn apply_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32, width: i32) {
+        use std::cmp::{max, min};
+        for y in min(y1, y2)..=max(y1, y2) {
+            self.carve(Point::new(x, y), width);
+        }
+    }
+
+    fn apply_routed_tunnel(
+        &mut self,
+        from: usize,
+        to: usize,
+        width: i32,
+        rng: &mut RandomNumberGenerator,
+    ) {
+        let (start, end) = (self.rooms[from].center(), self.rooms[to].center());
+        let mut planner = CorridorPlanner {
+            dimensions: Point::new(self.map.width, self.map.height),
+            blocked: vec![false; self.map.tiles.len()],
+        };
+        for (i, room) in self.rooms.iter().enumerate() {
+            if i != from && i != to {
+                // Keep a one tile margin so corridors don't merge into room walls
+                for p in room.tiles.iter() {
+                    Rect::with_size(p.x - 1, p.y - 1, 2, 2).for_each(|p| {
+                        if let Some(idx) = self.map.try_idx(p) {
+                            planner.blocked[idx] = true;
+                        }
+                    });
+                }
+            }
+        }
+
+        let path = a_star_search(
+            self.map.point2d_to_index(start),
+            self.map.point2d_to_index(end),
+            &planner,
+        );
+        if path.success {
+            for idx in path.steps {
+                self.carve(self.map.index_to_point2d(idx), width);
+            }
+        } else {
+            self.apply_l_tunnel(from, to, width, rng);
+        }
+    }
+
+    fn apply_drunken_tunnel(
+        &mut self,
+        from: usize,
+        to: usize,
+        width: i32,
+        rng: &mut RandomNumberGenerator,
+    ) {
+        let mut current = self.rooms[from].center();
+        let target = self.rooms[to].center();
+        let max_steps = 4 * ((current.x - target.x).abs() + (current.y - target.y).abs());
+
+        let mut steps = 0;
+        while current != target && steps < max_steps {
+            self.carve(current, width);
+            let delta = target - current;
+            // Two out of three steps head for the target, the rest wander
+            let step = if rng.range(0, 3) < 2 {
+                if delta.x != 0 && (delta.y == 0 || rng.range(0, 2) == 0) {
+                    Point::new(delta.x.signum(), 0)
+                } else {
+                    Point::new(0, delta.y.signum())
+                }
+            } else {
+                match rng.range(0, 4) {
+                    0 => Point::new(-1, 0),
+                    1 => Point::new(1, 0),
+                    2 => Point::new(0, -1),
+                    _ => Point::new(0, 1),
+                }
+            };
+            let next = current + step;
+            if self.map.is_interior(next) {
+                current = next;
+            }
+            steps += 1;
+        }
+
+        if current != target {
+            // Wandered too long, finish the job with a straight tunnel
+            self.apply_horizontal_tunnel(current.x, target.x, current.y, width);
+            self.apply_vertical_tunnel(current.y, target.y, target.x, width);
+        }
+        self.carve(target, width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Room indices reachable from room 0 along `edges`
+    fn connected_rooms(count: usize, edges: &[(usize, usize)]) -> usize {
+        let mut reached = vec![false; count];
+        reached[0] = true;
+        let mut open = vec![0];
+        while let Some(room) = open.pop() {
+            for (a, b) in edges.iter() {
+                let next = match (*a == room, *b == room) {
+                    (true, _) => *b,
+                    (_, true) => *a,
+                    _ => continue,
+                };
+                if !reached[next] {
+                    reached[next] = true;
+                    open.push(next);
+                }
+            }
+        }
+        reached.iter().filter(|r| **r).count()
+    }
+
+    #[test]
+    fn spanning_tree_connects_every_room_without_loops() {
+        for seed in 0..20 {
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            let mut mb = MapBuilder::blank(SCREEN_WIDTH, SCREEN_HEIGHT);
+            mb.build_random_rooms(&mut rng);
+            let count = mb.rooms.len();
+
+            let edges = mb.spanning_tree_edges(0, &mut rng);
+            assert_eq!(edges.len(), count - 1);
+            assert_eq!(connected_rooms(count, &edges), count);
+
+            let edges = mb.spanning_tree_edges(3, &mut rng);
+            assert_eq!(edges.len(), count + 2);
+            assert_eq!(connected_rooms(count, &edges), count);
+        }
+    }
+
+    #[test]
+    fn every_layout_and_routing_reaches_every_room() {
+        let layouts = [
+            CorridorLayout::SortedByX,
+            CorridorLayout::MinimumSpanningTree { extra_loops: 2 },
+            CorridorLayout::NearestNeighbour,
+        ];
+        let routings = [
+            CorridorRouting::LShaped,
+            CorridorRouting::AStar,
+            CorridorRouting::Drunken,
+        ];
+        for layout in layouts {
+            for routing in routings {
+                for width in 1..=2 {
+                    let mut rng = RandomNumberGenerator::seeded(7);
+                    let mut mb = MapBuilder::blank(SCREEN_WIDTH, SCREEN_HEIGHT);
+                    mb.build_random_rooms(&mut rng);
+                    mb.build_corridors(CorridorStyle { layout, routing, width }, &mut rng);
+                    mb.player_start = mb.rooms[0].center();
+
+                    let distances = mb.distance_map();
+                    for room in mb.rooms.iter() {
+                        let idx = mb.map.point2d_to_index(room.center());
+                        assert!(
+                            distances.map[idx] < f32::MAX,
+                            "{layout:?} {routing:?} width {width} left a room unreachable"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
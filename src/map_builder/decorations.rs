@@ -0,0 +1,108 @@
+use crate::prelude::*;
+use super::themes::{DecorationRules, DungeonTheme};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PropKind {
+    Rubble,
+    Bones,
+    Torch,
+    Banner,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Prop {
+    pub kind: PropKind,
+    pub position: Point,
+    // Wall-mounted props face the floor tile they are visible from
+    pub facing: Point,
+}
+
+pub fn apply_decorations(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
+    let rules = DungeonTheme::decoration_rules();
+    place_banners(mb, &rules);
+    place_torches(mb, rng, &rules);
+    place_floor_clutter(mb, rng, &rules);
+}
+
+fn is_floor(map: &Map, point: Point) -> bool {
+    map.try_idx(point)
+        .is_some_and(|idx| map.tiles[idx] == TileType::Floor)
+}
+
+fn is_wall(map: &Map, point: Point) -> bool {
+    map.try_idx(point)
+        .is_some_and(|idx| map.tiles[idx] == TileType::Wall)
+}
+
+fn place_banners(mb: &mut MapBuilder, rules: &DecorationRules) {
+    for room in mb.rooms.iter() {
+        if room.area() < rules.banner_min_room_area {
+            continue;
+        }
+        // Hang it above the northernmost floor tile in the room's centre column
+        let column = room.center().x;
+        let top = room.tiles.iter().filter(|p| p.x == column).map(|p| p.y).min();
+        let wall = Point::new(column, top.unwrap_or(room.bounds.y1) - 1);
+        if is_wall(&mb.map, wall) {
+            mb.props.push(Prop {
+                kind: PropKind::Banner,
+                position: wall,
+                facing: Point::new(0, 1),
+            });
+        }
+    }
+}
+
+fn place_torches(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator, rules: &DecorationRules) {
+    for idx in 0..mb.map.tiles.len() {
+        let position = mb.map.index_to_point2d(idx);
+        if mb.map.tiles[idx] != TileType::Wall
+            || mb.props.iter().any(|p| p.position == position)
+        {
+            continue;
+        }
+        let facing = CARDINALS
+            .iter()
+            .find(|delta| is_floor(&mb.map, position + **delta));
+        if let Some(facing) = facing {
+            if rng.range(0, 100) < rules.torch_chance {
+                mb.props.push(Prop {
+                    kind: PropKind::Torch,
+                    position,
+                    facing: *facing,
+                });
+            }
+        }
+    }
+}
+
+fn place_floor_clutter(
+    mb: &mut MapBuilder,
+    rng: &mut RandomNumberGenerator,
+    rules: &DecorationRules,
+) {
+    for idx in 0..mb.map.tiles.len() {
+        let position = mb.map.index_to_point2d(idx);
+        if mb.map.tiles[idx] != TileType::Floor
+            || position == mb.player_start
+            || position == mb.amulet_start
+            || mb.has_monster_at(position)
+            || mb.has_loot_at(position)
+        {
+            continue;
+        }
+        let roll = rng.range(0, 100);
+        let kind = if roll < rules.bones_chance {
+            PropKind::Bones
+        } else if roll < rules.bones_chance + rules.rubble_chance {
+            PropKind::Rubble
+        } else {
+            continue;
+        };
+        mb.props.push(Prop {
+            kind,
+            position,
+            facing: Point::zero(),
+        });
+    }
+}
@@ -0,0 +1,89 @@
+use crate::prelude::*;
+
+impl MapBuilder {
+    /// Turns single-tile corridor openings next to a room into doors. Only one tile wide
+    /// corridors qualify, so rooms joined with a wider `CorridorStyle::width` get no doors.
+    pub(super) fn place_doors(&mut self) {
+        let mut doors: Vec<Point> = Vec::new();
+        for room in self.rooms.iter() {
+            for tile in room.tiles.iter() {
+                for delta in CARDINALS {
+                    let candidate = *tile + delta;
+                    // Back to back doors in a short corridor would be one too many
+                    let crowded = doors.contains(&candidate)
+                        || CARDINALS.iter().any(|d| doors.contains(&(candidate + *d)));
+                    if !crowded && self.is_chokepoint(candidate) {
+                        doors.push(candidate);
+                    }
+                }
+            }
+        }
+
+        for door in doors {
+            let idx = self.map.point2d_to_index(door);
+            self.map.tiles[idx] = TileType::Door;
+        }
+    }
+
+    // A corridor tile outside every room, walled in on both sides
+    fn is_chokepoint(&self, point: Point) -> bool {
+        let is_tile = |p: Point, tile: TileType| {
+            self.map.try_idx(p).map(|idx| self.map.tiles[idx]) == Some(tile)
+        };
+        if !is_tile(point, TileType::Floor) || self.rooms.iter().any(|r| r.contains(point)) {
+            return false;
+        }
+        let walled_x = is_tile(point + Point::new(-1, 0), TileType::Wall)
+            && is_tile(point + Point::new(1, 0), TileType::Wall);
+        let walled_y = is_tile(point + Point::new(0, -1), TileType::Wall)
+            && is_tile(point + Point::new(0, 1), TileType::Wall);
+        walled_x != walled_y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A plain room over `from` to `to`, carved and added to the builder's rooms
+    fn add_room(mb: &mut MapBuilder, from: Point, to: Point) {
+        let bounds = Rect::with_exact(from.x, from.y, to.x, to.y);
+        let room = Room::new(bounds, RoomShape::Rectangle, &mut RandomNumberGenerator::seeded(0));
+        mb.carve_rect(from, to);
+        mb.rooms.push(room);
+    }
+
+    fn doors(mb: &MapBuilder) -> Vec<Point> {
+        (0..mb.map.tiles.len())
+            .filter(|idx| mb.map.tiles[*idx] == TileType::Door)
+            .map(|idx| mb.map.index_to_point2d(idx))
+            .collect()
+    }
+
+    #[test]
+    fn only_one_tile_wide_corridors_get_doors() {
+        let mut mb = MapBuilder::blank(20, 10);
+        add_room(&mut mb, Point::new(2, 2), Point::new(6, 6));
+        add_room(&mut mb, Point::new(12, 2), Point::new(16, 6));
+        mb.carve_rect(Point::new(7, 4), Point::new(11, 4));
+        mb.place_doors();
+        assert_eq!(doors(&mb), vec![Point::new(7, 4), Point::new(11, 4)]);
+
+        let mut mb = MapBuilder::blank(20, 10);
+        add_room(&mut mb, Point::new(2, 2), Point::new(6, 6));
+        add_room(&mut mb, Point::new(12, 2), Point::new(16, 6));
+        mb.carve_rect(Point::new(7, 4), Point::new(11, 5));
+        mb.place_doors();
+        assert!(doors(&mb).is_empty());
+    }
+
+    #[test]
+    fn short_corridors_get_a_single_door() {
+        let mut mb = MapBuilder::blank(20, 10);
+        add_room(&mut mb, Point::new(2, 2), Point::new(6, 6));
+        add_room(&mut mb, Point::new(9, 2), Point::new(13, 6));
+        mb.carve_rect(Point::new(7, 4), Point::new(8, 4));
+        mb.place_doors();
+        assert_eq!(doors(&mb), vec![Point::new(7, 4)]);
+    }
+}
@@ -3,27 +3,33 @@ use super::MapArchitect;
 
 pub struct EmptyArchitect {}
 
+// About fifty monsters on a full size map
+const MONSTER_DENSITY: f32 = 0.0125;
+
 impl MapArchitect for EmptyArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
+    fn new(
+        &mut self,
+        rng: &mut RandomNumberGenerator,
+        settings: &GenerationSettings,
+    ) -> Result<MapBuilder, MapGenError> {
         let mut mb = MapBuilder{
-            map : Map::new(),
+            map : Map::with_size(settings.width, settings.height),
             rooms: Vec::new(),
+            secret_rooms: Vec::new(),
+            depth : settings.depth,
             monster_spawns : Vec::new(),
+            loot : Vec::new(),
             player_start : Point::zero(),
             amulet_start : Point::zero(),
+            props : Vec::new(),
+            snapshots : settings.record_snapshots.then(Vec::new),
             // theme: super::themes::DungeonTheme::new()
         };
         mb.fill(TileType::Floor);
-        mb.player_start = Point::new(SCREEN_WIDTH/2, SCREEN_HEIGHT/2);
-        mb.amulet_start = mb.find_most_distant();
-        for _ in 0..50 {
-            mb.monster_spawns.push(
-                Point::new(
-                    rng.range(1, SCREEN_WIDTH),
-                    rng.range(1, SCREEN_WIDTH)
-                )
-            )
-        }
-        mb
+        mb.take_snapshot();
+        mb.player_start = Point::new(mb.map.width/2, mb.map.height/2);
+        mb.amulet_start = mb.find_most_distant()?;
+        mb.spawn_monsters(settings.monster_density.unwrap_or(MONSTER_DENSITY), rng);
+        Ok(mb)
     }
 }
\ No newline at end of file
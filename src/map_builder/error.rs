@@ -0,0 +1,25 @@
+use std::fmt;
+
+/// Why a map could not be generated. Another seed will usually do.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MapGenError {
+    // There is not a single floor tile to put the player on
+    NoFloor,
+    // Nothing can be reached from the player start
+    NoReachableTiles,
+    NoRooms,
+    AmuletUnreachable,
+}
+
+impl fmt::Display for MapGenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapGenError::NoFloor => write!(f, "the map has no floor tiles"),
+            MapGenError::NoReachableTiles => write!(f, "nothing is reachable from the start"),
+            MapGenError::NoRooms => write!(f, "no rooms could be placed"),
+            MapGenError::AmuletUnreachable => write!(f, "the amulet cannot be reached from the start"),
+        }
+    }
+}
+
+impl std::error::Error for MapGenError {}
@@ -0,0 +1,140 @@
+use crate::prelude::*;
+
+/// How many ways down a level gets.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ExitPlacement {
+    None,
+    // A single staircase on every level above the final one
+    Stairs,
+    // Several staircases spread apart, so the level branches towards them
+    Branching { count: usize },
+}
+
+impl ExitPlacement {
+    fn count(&self, depth: i32) -> usize {
+        // The final level ends with the amulet, not with more stairs
+        if depth >= FINAL_DEPTH {
+            return 0;
+        }
+        match self {
+            ExitPlacement::None => 0,
+            ExitPlacement::Stairs => 1,
+            ExitPlacement::Branching { count } => *count,
+        }
+    }
+}
+
+// Exits of a branching level closer than this would lead down the same path
+const MIN_EXIT_SPACING: f32 = 15.0;
+
+pub fn apply_exits(mb: &mut MapBuilder, exits: ExitPlacement) {
+    let count = exits.count(mb.depth);
+    if count == 0 {
+        return;
+    }
+
+    let dijkstra_map = mb.distance_map();
+    let mut candidates: Vec<(Point, f32)> = dijkstra_map
+        .map
+        .iter()
+        .enumerate()
+        .filter(|(idx, distance)| {
+            let p = mb.map.index_to_point2d(*idx);
+            **distance < f32::MAX
+                && mb.map.tiles[*idx] == TileType::Floor
+                && p != mb.player_start
+                && p != mb.amulet_start
+                && !mb.has_monster_at(p)
+                && !mb.has_loot_at(p)
+        })
+        .map(|(idx, distance)| (mb.map.index_to_point2d(idx), *distance))
+        .collect();
+    // Furthest first, ties broken by index so seeds stay reproducible
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut placed: Vec<Point> = Vec::new();
+    for (position, _) in candidates.iter() {
+        if placed.len() == count {
+            break;
+        }
+        let spaced = placed
+            .iter()
+            .all(|p| DistanceAlg::Pythagoras.distance2d(*p, *position) >= MIN_EXIT_SPACING);
+        if spaced {
+            placed.push(*position);
+        }
+    }
+    // A cramped map still gets every exit, just closer together
+    for (position, _) in candidates.iter() {
+        if placed.len() == count {
+            break;
+        }
+        if !placed.contains(position) {
+            placed.push(*position);
+        }
+    }
+
+    for position in placed {
+        let idx = mb.map.point2d_to_index(position);
+        mb.map.tiles[idx] = TileType::Exit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exits(mb: &MapBuilder) -> Vec<Point> {
+        (0..mb.map.tiles.len())
+            .filter(|idx| mb.map.tiles[*idx] == TileType::Exit)
+            .map(|idx| mb.map.index_to_point2d(idx))
+            .collect()
+    }
+
+    fn open_cave() -> MapBuilder {
+        let mut mb = MapBuilder::blank(60, 40);
+        mb.carve_rect(Point::new(1, 1), Point::new(58, 38));
+        mb.player_start = Point::new(1, 1);
+        mb.amulet_start = Point::new(58, 38);
+        mb
+    }
+
+    #[test]
+    fn branching_exits_are_spaced_apart() {
+        let mut mb = open_cave();
+        apply_exits(&mut mb, ExitPlacement::Branching { count: 3 });
+        let placed = exits(&mb);
+        assert_eq!(placed.len(), 3);
+        for (i, a) in placed.iter().enumerate() {
+            assert!(*a != mb.player_start && *a != mb.amulet_start);
+            for b in placed.iter().skip(i + 1) {
+                assert!(DistanceAlg::Pythagoras.distance2d(*a, *b) >= MIN_EXIT_SPACING);
+            }
+        }
+    }
+
+    #[test]
+    fn cramped_maps_still_get_every_exit() {
+        let mut mb = MapBuilder::blank(20, 5);
+        mb.carve_rect(Point::new(1, 2), Point::new(12, 2));
+        mb.player_start = Point::new(1, 2);
+        mb.amulet_start = Point::new(1, 2);
+        apply_exits(&mut mb, ExitPlacement::Branching { count: 3 });
+        assert_eq!(exits(&mb).len(), 3);
+    }
+
+    #[test]
+    fn final_level_and_none_get_no_exits() {
+        let mut mb = open_cave();
+        apply_exits(&mut mb, ExitPlacement::None);
+        assert!(exits(&mb).is_empty());
+
+        mb.depth = FINAL_DEPTH;
+        apply_exits(&mut mb, ExitPlacement::Stairs);
+        assert!(exits(&mb).is_empty());
+
+        mb.depth = FINAL_DEPTH - 1;
+        apply_exits(&mut mb, ExitPlacement::Stairs);
+        assert_eq!(exits(&mb).len(), 1);
+    }
+}
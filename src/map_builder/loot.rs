@@ -0,0 +1,111 @@
+use crate::prelude::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LootKind {
+    HealingPotion,
+    ManaPotion,
+    Gold { amount: i32 },
+    Sword,
+    Shield,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LootSpawn {
+    pub position: Point,
+    pub kind: LootKind,
+}
+
+// Nothing is placed this close to the start, so the first room isn't a free shop
+const MIN_START_DISTANCE: f32 = 6.0;
+const LOOT_DENSITY: f32 = 0.01;
+
+fn roll_loot(depth: i32, far: bool, rng: &mut RandomNumberGenerator) -> LootKind {
+    // Equipment only turns up in the far half of the level
+    let total = if far { 20 } else { 18 };
+    match rng.range(0, total) {
+        0..=5 => LootKind::HealingPotion,
+        6..=9 => LootKind::ManaPotion,
+        10..=17 => LootKind::Gold {
+            amount: rng.range(5, 15) * depth,
+        },
+        18 => LootKind::Sword,
+        _ => LootKind::Shield,
+    }
+}
+
+/// Loot for `$` glyphs in vaults, always better than what lies on the floor.
+pub(super) fn roll_treasure(depth: i32, rng: &mut RandomNumberGenerator) -> LootKind {
+    match rng.range(0, 3) {
+        0 => LootKind::Sword,
+        1 => LootKind::Shield,
+        _ => LootKind::Gold {
+            amount: rng.range(30, 60) * depth,
+        },
+    }
+}
+
+pub fn apply_loot(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
+    let dijkstra_map = mb.distance_map();
+    let furthest = dijkstra_map
+        .map
+        .iter()
+        .filter(|d| **d < f32::MAX)
+        .fold(0.0f32, |a, b| a.max(*b));
+
+    let eligible = |mb: &MapBuilder, p: Point| {
+        let idx = mb.map.point2d_to_index(p);
+        mb.map.tiles[idx] == TileType::Floor
+            && dijkstra_map.map[idx] < f32::MAX
+            && dijkstra_map.map[idx] >= MIN_START_DISTANCE
+            && p != mb.amulet_start
+            && !mb.has_monster_at(p)
+            && !mb.has_loot_at(p)
+    };
+
+    let mut placements = Vec::new();
+    if mb.rooms.is_empty() {
+        let candidates: Vec<Point> = (0..mb.map.tiles.len())
+            .map(|idx| mb.map.index_to_point2d(idx))
+            .filter(|p| eligible(mb, *p))
+            .collect();
+        let count = (candidates.len() as f32 * LOOT_DENSITY) as usize;
+        for _ in 0..count {
+            if let Some(i) = rng.random_slice_index(&candidates) {
+                placements.push(candidates[i]);
+            }
+        }
+    } else {
+        // One item in about half of the rooms, big rooms get a second chance
+        for room in mb.rooms.iter() {
+            let candidates: Vec<Point> = room
+                .tiles
+                .iter()
+                .copied()
+                .filter(|p| eligible(mb, *p))
+                .collect();
+            let rolls = if room.area() > 40 { 2 } else { 1 };
+            for _ in 0..rolls {
+                if rng.range(0, 2) == 0 {
+                    if let Some(i) = rng.random_slice_index(&candidates) {
+                        placements.push(candidates[i]);
+                    }
+                }
+            }
+        }
+    }
+
+    for position in placements {
+        if mb.has_loot_at(position) {
+            continue;
+        }
+        let far = dijkstra_map.map[mb.map.point2d_to_index(position)] > furthest / 2.0;
+        let kind = roll_loot(mb.depth, far, rng);
+        mb.loot.push(LootSpawn { position, kind });
+    }
+}
+
+impl MapBuilder {
+    pub fn has_loot_at(&self, position: Point) -> bool {
+        self.loot.iter().any(|l| l.position == position)
+    }
+}
@@ -0,0 +1,176 @@
+use crate::prelude::*;
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct MapMetrics {
+    pub floor_ratio: f32,
+    pub regions: usize,
+    // None when the amulet cannot be reached from the start
+    pub start_to_amulet: Option<f32>,
+    pub dead_ends: usize,
+    pub exits: usize,
+    pub room_count: usize,
+    pub room_sizes: Vec<usize>,
+    pub spawn_density: f32,
+}
+
+impl MapMetrics {
+    pub fn smallest_room(&self) -> Option<usize> {
+        self.room_sizes.iter().min().copied()
+    }
+
+    pub fn largest_room(&self) -> Option<usize> {
+        self.room_sizes.iter().max().copied()
+    }
+
+    pub fn mean_room_size(&self) -> Option<f32> {
+        if self.room_sizes.is_empty() {
+            None
+        } else {
+            Some(self.room_sizes.iter().sum::<usize>() as f32 / self.room_sizes.len() as f32)
+        }
+    }
+}
+
+impl fmt::Display for MapMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "floor ratio:     {:.3}", self.floor_ratio)?;
+        writeln!(f, "regions:         {}", self.regions)?;
+        match self.start_to_amulet {
+            Some(distance) => writeln!(f, "start to amulet: {distance}")?,
+            None => writeln!(f, "start to amulet: unreachable")?,
+        }
+        writeln!(f, "dead ends:       {}", self.dead_ends)?;
+        writeln!(f, "exits:           {}", self.exits)?;
+        writeln!(f, "rooms:           {}", self.room_count)?;
+        if let (Some(min), Some(max), Some(mean)) =
+            (self.smallest_room(), self.largest_room(), self.mean_room_size())
+        {
+            writeln!(f, "room sizes:      min {min} / max {max} / mean {mean:.1}")?;
+        }
+        write!(f, "spawn density:   {:.3}", self.spawn_density)
+    }
+}
+
+impl MapBuilder {
+    pub fn metrics(&self) -> MapMetrics {
+        let walkable = (0..self.map.tiles.len())
+            .filter(|idx| self.map.can_enter_tile(self.map.index_to_point2d(*idx)))
+            .count();
+
+        let dijkstra_map = self.distance_map();
+        let start_to_amulet = match dijkstra_map.map[self.map.point2d_to_index(self.amulet_start)] {
+            distance if distance < f32::MAX => Some(distance),
+            _ => None,
+        };
+
+        MapMetrics {
+            floor_ratio: walkable as f32 / self.map.tiles.len() as f32,
+            regions: self.count_regions(),
+            start_to_amulet,
+            dead_ends: self.count_dead_ends(),
+            exits: self
+                .map
+                .tiles
+                .iter()
+                .filter(|t| **t == TileType::Exit)
+                .count(),
+            room_count: self.rooms.len(),
+            room_sizes: self.rooms.iter().map(|r| r.area()).collect(),
+            spawn_density: if walkable == 0 {
+                0.0
+            } else {
+                self.monster_spawns.len() as f32 / walkable as f32
+            },
+        }
+    }
+
+    fn count_regions(&self) -> usize {
+        // Secret rooms sit behind hidden doors but are still part of the level
+        let passable = |p: Point| {
+            self.map.can_enter_tile(p)
+                || self.map.try_idx(p).map(|idx| self.map.tiles[idx]) == Some(TileType::HiddenDoor)
+        };
+        let mut visited = vec![false; self.map.tiles.len()];
+        let mut regions = 0;
+        for start in 0..self.map.tiles.len() {
+            if visited[start] || !passable(self.map.index_to_point2d(start)) {
+                continue;
+            }
+            regions += 1;
+            visited[start] = true;
+            let mut open = vec![start];
+            while let Some(idx) = open.pop() {
+                let location = self.map.index_to_point2d(idx);
+                for delta in CARDINALS.iter() {
+                    let next = location + *delta;
+                    if !passable(next) {
+                        continue;
+                    }
+                    let next = self.map.point2d_to_index(next);
+                    if !visited[next] {
+                        visited[next] = true;
+                        open.push(next);
+                    }
+                }
+            }
+        }
+        regions
+    }
+
+    fn count_dead_ends(&self) -> usize {
+        (0..self.map.tiles.len())
+            .filter(|idx| {
+                self.map.can_enter_tile(self.map.index_to_point2d(*idx))
+                    && self.map.get_available_exits(*idx).len() == 1
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A corridor with a branch off it, ending in a door and a hidden door, and a room
+    // cut off from both
+    fn hand_carved() -> MapBuilder {
+        let mut mb = MapBuilder::blank(20, 10);
+        mb.carve_rect(Point::new(1, 1), Point::new(10, 1));
+        mb.carve_rect(Point::new(5, 2), Point::new(5, 4));
+        mb.carve_rect(Point::new(14, 5), Point::new(16, 7));
+        let idx = mb.map.idx(10, 1);
+        mb.map.tiles[idx] = TileType::Door;
+        let idx = mb.map.idx(11, 1);
+        mb.map.tiles[idx] = TileType::HiddenDoor;
+        mb.player_start = Point::new(1, 1);
+        mb.amulet_start = Point::new(5, 4);
+        mb
+    }
+
+    #[test]
+    fn floor_ratio_only_counts_enterable_tiles() {
+        let metrics = hand_carved().metrics();
+        assert_eq!(metrics.floor_ratio, 22.0 / 200.0);
+        assert_eq!(metrics.start_to_amulet, Some(7.0));
+    }
+
+    #[test]
+    fn regions_and_dead_ends_match_the_carving() {
+        let mb = hand_carved();
+        assert_eq!(mb.count_regions(), 2);
+        // Both corridor ends and the end of the branch
+        assert_eq!(mb.count_dead_ends(), 3);
+    }
+
+    #[test]
+    fn hidden_doors_join_secret_rooms_to_their_region() {
+        let mut mb = hand_carved();
+        mb.carve_rect(Point::new(12, 1), Point::new(13, 2));
+        assert_eq!(mb.count_regions(), 2);
+
+        let idx = mb.map.idx(11, 1);
+        mb.map.tiles[idx] = TileType::Wall;
+        assert_eq!(mb.count_regions(), 3);
+    }
+}
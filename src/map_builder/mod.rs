@@ -5,153 +5,284 @@ mod automata;
 mod random_walk;
 mod prefab;
 mod themes;
+mod decorations;
+mod metrics;
+mod corridors;
+mod room;
+mod doors;
+mod spawns;
+mod loot;
+mod exits;
+mod secrets;
+mod error;
 
-use self::{automata::CellularAutomataArchitect, random_walk::RandomWalkArchitect, rooms::RoomsArchitect, prefab::apply_prefab,};
+use self::{automata::CellularAutomataArchitect, empty::EmptyArchitect, random_walk::RandomWalkArchitect, rooms::RoomsArchitect, prefab::apply_prefab, decorations::apply_decorations, loot::apply_loot, exits::apply_exits, secrets::apply_secret_rooms};
+pub use self::decorations::{Prop, PropKind};
+pub use self::metrics::MapMetrics;
+pub use self::corridors::{CorridorLayout, CorridorRouting, CorridorStyle};
+pub use self::room::{Room, RoomShape};
+pub use self::loot::{LootKind, LootSpawn};
+pub use self::error::MapGenError;
+pub use self::exits::ExitPlacement;
+pub use self::spawns::{roll_monster, DifficultyBand, MonsterKind, MonsterSpawn, SpawnTableEntry};
 
 trait MapArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder;
+    fn new(
+        &mut self,
+        rng: &mut RandomNumberGenerator,
+        settings: &GenerationSettings,
+    ) -> Result<MapBuilder, MapGenError>;
 }
 
 // pub trait MapTheme: Sync+Send {
 //     fn tile_to_render(&self, tile_type: TileType) -> FontCharType;
 // }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ArchitectKind {
+    Empty,
+    RandomWalk,
+    Rooms(CorridorStyle),
+    CellularAutomata,
+}
+
+impl ArchitectKind {
+    pub fn random(rng: &mut RandomNumberGenerator) -> Self {
+        match rng.range(0,3) {
+            0 => ArchitectKind::RandomWalk,
+            1 => ArchitectKind::Rooms(CorridorStyle::random(rng)),
+            _ => ArchitectKind::CellularAutomata
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GenerationSettings {
+    // Dungeon level, starting at 1, that spawn tables are keyed by
+    pub depth: i32,
+    pub record_snapshots: bool,
+    pub exits: ExitPlacement,
+    pub secret_rooms: usize,
+    // Share of the reachable floor that gets a monster, `None` for the architect's own
+    pub monster_density: Option<f32>,
+    // Map size in tiles, the game itself always plays at the screen size
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for GenerationSettings {
+    fn default() -> Self {
+        Self {
+            depth: 1,
+            record_snapshots: false,
+            exits: ExitPlacement::Stairs,
+            secret_rooms: 1,
+            monster_density: None,
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+        }
+    }
+}
+
 const NUM_ROOMS: usize = 20;
+const MAX_ROOM_ATTEMPTS: usize = 1000;
 pub struct MapBuilder {
     pub map: Map,
-    pub rooms: Vec<Rect>,
-    pub monster_spawns: Vec<Point>,
+    pub rooms: Vec<Room>,
+    // Only reachable through a hidden door, never part of `rooms`
+    pub secret_rooms: Vec<Room>,
+    pub depth: i32,
+    pub monster_spawns: Vec<MonsterSpawn>,
+    pub loot: Vec<LootSpawn>,
     pub player_start: Point,
     pub amulet_start: Point,
+    pub props: Vec<Prop>,
+    // Intermediate maps recorded while generating, oldest first
+    pub snapshots: Option<Vec<Map>>,
     // pub theme: Box<dyn MapTheme>
 }
 
 impl MapBuilder {
-    pub fn new(rng: &mut RandomNumberGenerator) -> Self {
-        let mut architect: Box<dyn MapArchitect> = match rng.range(0,3) {
-            0 => Box::new(RandomWalkArchitect{}),
-            1 => Box::new(RoomsArchitect{}),
-            _ => Box::new(CellularAutomataArchitect{})
+    pub fn new(rng: &mut RandomNumberGenerator) -> Result<Self, MapGenError> {
+        Self::with_architect(ArchitectKind::random(rng), rng)
+    }
+
+    pub fn with_architect(
+        kind: ArchitectKind,
+        rng: &mut RandomNumberGenerator,
+    ) -> Result<Self, MapGenError> {
+        Self::with_settings(kind, rng, &GenerationSettings::default())
+    }
+
+    /// Runs `kind` and every generation step after it. An unlucky seed gives an error
+    /// rather than a broken map, so callers can simply try another one.
+    pub fn with_settings(
+        kind: ArchitectKind,
+        rng: &mut RandomNumberGenerator,
+        settings: &GenerationSettings,
+    ) -> Result<Self, MapGenError> {
+        let mut architect: Box<dyn MapArchitect> = match kind {
+            ArchitectKind::Empty => Box::new(EmptyArchitect{}),
+            ArchitectKind::RandomWalk => Box::new(RandomWalkArchitect{}),
+            ArchitectKind::Rooms(corridors) => Box::new(RoomsArchitect{ corridors }),
+            ArchitectKind::CellularAutomata => Box::new(CellularAutomataArchitect{})
         };
-        let mut mb = architect.new(rng);
+        let mut mb = architect.new(rng, settings)?;
         apply_prefab(&mut mb, rng);
+        apply_secret_rooms(&mut mb, settings.secret_rooms, rng);
+        apply_exits(&mut mb, settings.exits);
+        mb.take_snapshot();
+        apply_loot(&mut mb, rng);
+        apply_decorations(&mut mb, rng);
+
+        let amulet_idx = mb.map.point2d_to_index(mb.amulet_start);
+        if mb.distance_map().map[amulet_idx] == f32::MAX {
+            return Err(MapGenError::AmuletUnreachable);
+        }
 
         // mb.theme = match rng.range(0,2) {
         //     0=> DungeonTheme::new(),
         //     _=> ForestTheme::new(),
         // };
-        mb
+        Ok(mb)
+    }
+
+    fn take_snapshot(&mut self) {
+        if let Some(snapshots) = &mut self.snapshots {
+            snapshots.push(self.map.clone());
+        }
     }
 
     fn fill(&mut self, tile: TileType) {
         self.map.tiles.iter_mut().for_each(|t| *t = tile);
     }
 
-    fn find_most_distant(&self) -> Point {
-        let dijkstra_map = DijkstraMap::new(
-            SCREEN_WIDTH,
-            SCREEN_HEIGHT,
-            &vec![self.map.point2d_to_index(self.player_start)],
+    // Walking distance from the player start to every tile
+    fn distance_map(&self) -> DijkstraMap {
+        DijkstraMap::new(
+            self.map.width,
+            self.map.height,
+            &[self.map.point2d_to_index(self.player_start)],
             &self.map,
             1024.0,
-        );
+        )
+    }
+
+    fn find_most_distant(&self) -> Result<Point, MapGenError> {
+        let dijkstra_map = self.distance_map();
 
         const UNREACHABLE: &f32 = &f32::MAX;
-        self.map.index_to_point2d(
-            dijkstra_map
-                .map
-                .iter()
-                .enumerate()
-                .filter(|(_, dist)| *dist < UNREACHABLE)
-                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-                .unwrap()
-                .0,
-        )
+        dijkstra_map
+            .map
+            .iter()
+            .enumerate()
+            .filter(|(_, dist)| *dist < UNREACHABLE)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .filter(|(_, dist)| **dist > 0.0)
+            .map(|(idx, _)| self.map.index_to_point2d(idx))
+            .ok_or(MapGenError::NoReachableTiles)
     }
 
     fn build_random_rooms(&mut self, rng: &mut RandomNumberGenerator) {
-        while self.rooms.len() < NUM_ROOMS {
-            let room = Rect::with_size(
-                rng.range(1, SCREEN_WIDTH - 10),
-                rng.range(1, SCREEN_HEIGHT - 10),
+        // Small maps may not fit them all
+        let mut attempts = 0;
+        while self.rooms.len() < NUM_ROOMS && attempts < MAX_ROOM_ATTEMPTS {
+            attempts += 1;
+            let bounds = Rect::with_size(
+                rng.range(1, self.map.width - 10),
+                rng.range(1, self.map.height - 10),
                 rng.range(2, 10),
                 rng.range(2, 10),
             );
             let mut overlap = false;
             for r in self.rooms.iter() {
-                if r.intersect(&room) {
+                if r.bounds.intersect(&bounds) {
                     overlap = true;
                 }
             }
             if !overlap {
-                room.for_each(|p| {
-                    if p.x > 0 && p.x < SCREEN_WIDTH && p.y > 0 && p.y < SCREEN_HEIGHT {
-                        let idx = map_idx(p.x, p.y);
-                        self.map.tiles[idx] = TileType::Floor;
-                    }
-                });
-
-                self.rooms.push(room)
+                let room = Room::random(bounds, rng);
+                for p in room.tiles.iter() {
+                    let idx = self.map.idx(p.x, p.y);
+                    self.map.tiles[idx] = TileType::Floor;
+                }
+
+                self.rooms.push(room);
+                self.take_snapshot();
             }
         }
     }
+}
 
-    fn apply_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
-        use std::cmp::{max, min};
-        for x in min(x1, x2)..=max(x1, x2) {
-            if let Some(idx) = self.map.try_idx(Point::new(x, y)) {
-                self.map.tiles[idx as usize] = TileType::Floor;
-            }
-        }
+#[cfg(test)]
+impl MapBuilder {
+    // Solid rock with nothing placed yet, for tests to carve into
+    fn blank(width: i32, height: i32) -> Self {
+        let mut mb = MapBuilder {
+            map: Map::with_size(width, height),
+            rooms: Vec::new(),
+            secret_rooms: Vec::new(),
+            depth: 1,
+            monster_spawns: Vec::new(),
+            loot: Vec::new(),
+            player_start: Point::zero(),
+            amulet_start: Point::zero(),
+            props: Vec::new(),
+            snapshots: None,
+        };
+        mb.fill(TileType::Wall);
+        mb
     }
 
-    fn apply_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32) {
-        use std::cmp::{max, min};
-        for y in min(y1, y2)..=max(y1, y2) {
-            if let Some(idx) = self.map.try_idx(Point::new(x, y)) {
-                self.map.tiles[idx as usize] = TileType::Floor;
+    // Floor from `from` to `to`, both corners included
+    fn carve_rect(&mut self, from: Point, to: Point) {
+        for y in from.y..=to.y {
+            for x in from.x..=to.x {
+                let idx = self.map.idx(x, y);
+                self.map.tiles[idx] = TileType::Floor;
             }
         }
     }
+}
 
-    fn build_corridors(&mut self, rng: &mut RandomNumberGenerator) {
-        let mut rooms = self.rooms.clone();
-        rooms.sort_by(|a, b| a.center().x.cmp(&b.center().x));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        for (i, room) in rooms.iter().enumerate().skip(1) {
-            let prev = rooms[i - 1].center();
-            let new = room.center();
+    #[test]
+    fn a_sealed_start_has_nothing_reachable() {
+        let mut mb = MapBuilder::blank(20, 20);
+        mb.carve_rect(Point::new(5, 5), Point::new(5, 5));
+        mb.player_start = Point::new(5, 5);
+        assert_eq!(mb.find_most_distant(), Err(MapGenError::NoReachableTiles));
 
-            if rng.range(0, 2) == 1 {
-                self.apply_horizontal_tunnel(prev.x, new.x, prev.y);
-                self.apply_vertical_tunnel(prev.y, new.y, new.x);
-            } else {
-                self.apply_vertical_tunnel(prev.y, new.y, prev.x);
-                self.apply_horizontal_tunnel(prev.x, new.x, new.y);
-            }
+        mb.carve_rect(Point::new(5, 5), Point::new(12, 5));
+        assert_eq!(mb.find_most_distant(), Ok(Point::new(12, 5)));
+    }
+
+    #[test]
+    fn the_empty_fallback_always_generates() {
+        for seed in 0..20 {
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            assert!(MapBuilder::with_architect(ArchitectKind::Empty, &mut rng).is_ok());
         }
     }
-    fn spawn_monsters(&self, start: &Point, rng: &mut RandomNumberGenerator) -> Vec<Point> {
-        const NUM_MONSTERS: usize = 50;
-        let mut spawnable_tiles: Vec<Point> = self
-            .map
-            .tiles
-            .iter()
-            .enumerate()
-            .filter(|(idx, t)| {
-                **t == TileType::Floor
-                    && DistanceAlg::Pythagoras.distance2d(*start, self.map.index_to_point2d(*idx))
-                        > 10.0
-            })
-            .map(|(idx, _)| self.map.index_to_point2d(idx))
-            .collect();
 
-        let mut spawns = Vec::new();
-        for _ in 0..NUM_MONSTERS {
-            let target_index = rng.random_slice_index(&spawnable_tiles).unwrap();
-            spawns.push(spawnable_tiles[target_index].clone());
-            spawnable_tiles.remove(target_index);
+    #[test]
+    fn generated_maps_always_reach_the_amulet() {
+        let kinds = [
+            ArchitectKind::RandomWalk,
+            ArchitectKind::Rooms(CorridorStyle::default()),
+            ArchitectKind::CellularAutomata,
+        ];
+        for kind in kinds {
+            for seed in 0..30 {
+                let mut rng = RandomNumberGenerator::seeded(seed);
+                if let Ok(mb) = MapBuilder::with_architect(kind, &mut rng) {
+                    let amulet = mb.map.point2d_to_index(mb.amulet_start);
+                    assert!(mb.distance_map().map[amulet] < f32::MAX);
+                }
+            }
         }
-        spawns
     }
 }
@@ -1,14 +1,15 @@
 use crate::prelude::*;
+use super::loot::roll_treasure;
 
 const FORTRESS : (&str, i32, i32) = ("
 ------------
 ---######---
 ---#----#---
 ---#-M--#---
--###----###-
+-###-$$-###-
 --M------M--
 -###----###-
----#----#---
+---#-$--#---
 ---#----#---
 ---######---
 ------------
@@ -18,18 +19,19 @@ pub fn apply_prefab(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
     let mut placement = None;
 
     let dijkstra_map = DijkstraMap::new(
-        SCREEN_WIDTH,
-        SCREEN_HEIGHT,
+        mb.map.width,
+        mb.map.height,
         &vec![mb.map.point2d_to_index(mb.player_start)],
         &mb.map,
         1024.0
     );
 
+    let bands = mb.difficulty_bands();
     let mut attempts = 0;
     while placement.is_none() && attempts < 10 {
         let dimensions = Rect::with_size(
-            rng.range(0, SCREEN_WIDTH - FORTRESS.1),
-            rng.range(0, SCREEN_HEIGHT - FORTRESS.2),
+            rng.range(0, mb.map.width - FORTRESS.1),
+            rng.range(0, mb.map.height - FORTRESS.2),
             FORTRESS.1,
             FORTRESS.2
         );
@@ -43,10 +45,15 @@ pub fn apply_prefab(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
             }
         });
 
+        let points = dimensions.point_set();
+        if mb.rooms.iter().any(|room| room.tiles.iter().any(|pt| points.contains(pt))) {
+            can_place = false;
+        }
+
         if can_place {
             placement = Some(Point::new(dimensions.x1, dimensions.y1));
-            let points = dimensions.point_set();
-            mb.monster_spawns.retain(|pt| !points.contains(pt) );
+            mb.monster_spawns.retain(|spawn| !points.contains(&spawn.position) );
+            mb.loot.retain(|loot| !points.contains(&loot.position) );
         }
         attempts += 1;
     }
@@ -58,12 +65,18 @@ pub fn apply_prefab(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
         let mut i = 0;
         for ty in placement.y .. placement.y + FORTRESS.2 {
             for tx in placement.x .. placement.x + FORTRESS.1 {
-                let idx = map_idx(tx, ty);
+                let idx = mb.map.idx(tx, ty);
                 let c = string_vec[i];
                 match c {
                     'M' => {
                         mb.map.tiles[idx] = TileType::Floor;
-                        mb.monster_spawns.push(Point::new(tx, ty));
+                        let position = Point::new(tx, ty);
+                        mb.spawn_monster(position, bands.band_at(position), rng);
+                    }
+                    '$' => {
+                        mb.map.tiles[idx] = TileType::Floor;
+                        let kind = roll_treasure(mb.depth, rng);
+                        mb.loot.push(LootSpawn { position: Point::new(tx, ty), kind });
                     }
                     '-' => mb.map.tiles[idx] = TileType::Floor,
                     '#' => mb.map.tiles[idx] = TileType::Wall,
@@ -4,36 +4,46 @@ use crate::prelude::*;
 pub struct RandomWalkArchitect {}
 
 const MAX_WALKING_DISTANCE: usize = 400;
-const NUM_TILES: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
-const DESIRED_FLOOR : usize = NUM_TILES / 3;
+const MONSTER_DENSITY: f32 = 0.04;
 
 impl MapArchitect for RandomWalkArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
+    fn new(
+        &mut self,
+        rng: &mut RandomNumberGenerator,
+        settings: &GenerationSettings,
+    ) -> Result<MapBuilder, MapGenError> {
         let mut mb = MapBuilder {
-            map: Map::new(),
+            map: Map::with_size(settings.width, settings.height),
             rooms: Vec::new(),
+            secret_rooms: Vec::new(),
+            depth: settings.depth,
             monster_spawns: Vec::new(),
+            loot: Vec::new(),
             player_start: Point::zero(),
             amulet_start: Point::zero(),
+            props: Vec::new(),
+            snapshots: settings.record_snapshots.then(Vec::new),
             // theme: super::themes::DungeonTheme::new()
         };
         mb.fill(TileType::Wall);
-        let center = Point::new(SCREEN_WIDTH /2, SCREEN_HEIGHT/2);
+        let center = Point::new(mb.map.width /2, mb.map.height/2);
+        let desired_floor = mb.map.tiles.len() / 3;
         self.walk(&center, rng, &mut mb.map);
+        mb.take_snapshot();
         while mb.map.tiles.iter()
-            .filter(|t| **t == TileType::Floor).count() < DESIRED_FLOOR
+            .filter(|t| **t == TileType::Floor).count() < desired_floor
         {
             self.walk(
                 &Point::new(
-                    rng.range(0, SCREEN_WIDTH),
-                    rng.range(0, SCREEN_HEIGHT)
+                    rng.range(0, mb.map.width),
+                    rng.range(0, mb.map.height)
                 ),
                 rng,
                 &mut mb.map
             );
             let dijkstra_map = DijkstraMap::new(// (3)
-                SCREEN_WIDTH,
-                SCREEN_HEIGHT,
+                mb.map.width,
+                mb.map.height,
                 &vec![mb.map.point2d_to_index(center)],
                 &mb.map,
                 1024.0
@@ -43,11 +53,12 @@ impl MapArchitect for RandomWalkArchitect {
                 .enumerate()
                 .filter(|(_, distance)| *distance > &2000.0)
                 .for_each(|(idx, _)| mb.map.tiles[idx] = TileType::Wall);
+            mb.take_snapshot();
         }
-        mb.monster_spawns = mb.spawn_monsters(&center, rng);
         mb.player_start = center;
-        mb.amulet_start = mb.find_most_distant();
-        mb
+        mb.amulet_start = mb.find_most_distant()?;
+        mb.spawn_monsters(settings.monster_density.unwrap_or(MONSTER_DENSITY), rng);
+        Ok(mb)
     }
 }
 impl RandomWalkArchitect {
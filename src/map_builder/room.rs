@@ -0,0 +1,204 @@
+use crate::prelude::*;
+use std::collections::HashSet;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RoomShape {
+    Rectangle,
+    Circle,
+    Cross,
+    LShaped,
+    Cavern,
+    Pillared,
+}
+
+impl RoomShape {
+    // Shapes that need some room to read as anything but a rectangle
+    fn random(rng: &mut RandomNumberGenerator, bounds: &Rect) -> Self {
+        let smallest = bounds.width().min(bounds.height());
+        if smallest < 4 {
+            return RoomShape::Rectangle;
+        }
+        match rng.range(0, 8) {
+            0 => RoomShape::Circle,
+            1 => RoomShape::Cross,
+            2 => RoomShape::LShaped,
+            3 => RoomShape::Cavern,
+            4 if smallest >= 5 => RoomShape::Pillared,
+            _ => RoomShape::Rectangle,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Room {
+    pub bounds: Rect,
+    pub shape: RoomShape,
+    // The floor tiles that actually belong to the room in row order, always inside `bounds`
+    pub tiles: Vec<Point>,
+}
+
+impl Room {
+    /// Callers keep `bounds` off the map edge, which is never carved.
+    pub fn new(bounds: Rect, shape: RoomShape, rng: &mut RandomNumberGenerator) -> Self {
+        let mut tiles = Vec::new();
+        let center = bounds.center();
+        let half_width = bounds.width() as f32 / 2.0;
+        let half_height = bounds.height() as f32 / 2.0;
+        bounds.for_each(|p| {
+            let keep = match shape {
+                RoomShape::Rectangle | RoomShape::Cavern => true,
+                RoomShape::Circle => {
+                    let dx = (p.x - center.x) as f32 / (half_width + 0.5);
+                    let dy = (p.y - center.y) as f32 / (half_height + 0.5);
+                    dx * dx + dy * dy <= 1.0
+                }
+                RoomShape::Cross => {
+                    (p.x - center.x).abs() <= bounds.width() / 4
+                        || (p.y - center.y).abs() <= bounds.height() / 4
+                }
+                RoomShape::LShaped => p.x <= center.x || p.y >= center.y,
+                RoomShape::Pillared => {
+                    let (x, y) = (p.x - bounds.x1, p.y - bounds.y1);
+                    let inner = x > 0 && x < bounds.width() - 1 && y > 0 && y < bounds.height() - 1;
+                    !(inner && x % 2 == 0 && y % 2 == 0)
+                }
+            };
+            if keep {
+                tiles.push(p);
+            }
+        });
+
+        let mut room = Self { bounds, shape, tiles };
+        if shape == RoomShape::Cavern {
+            room.erode(rng);
+        }
+        room
+    }
+
+    pub fn random(bounds: Rect, rng: &mut RandomNumberGenerator) -> Self {
+        let shape = RoomShape::random(rng, &bounds);
+        Self::new(bounds, shape, rng)
+    }
+
+    /// The room tile closest to the middle of the bounding rectangle.
+    pub fn center(&self) -> Point {
+        let middle = self.bounds.center();
+        self.tiles
+            .iter()
+            .min_by_key(|p| (p.x - middle.x).pow(2) + (p.y - middle.y).pow(2))
+            .copied()
+            .unwrap_or(middle)
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        self.tiles.contains(&point)
+    }
+
+    pub fn area(&self) -> usize {
+        self.tiles.len()
+    }
+
+    // Knock random holes into the room, smooth them like the cellular automata
+    // architect does, and keep whatever is still connected to the centre.
+    fn erode(&mut self, rng: &mut RandomNumberGenerator) {
+        let center = self.center();
+        let noisy: HashSet<Point> = self
+            .tiles
+            .iter()
+            .filter(|p| **p == center || rng.range(0, 100) > 35)
+            .copied()
+            .collect();
+        let smoothed: Vec<Point> = self
+            .tiles
+            .iter()
+            .filter(|p| {
+                let neighbours = (-1..=1)
+                    .flat_map(|y| (-1..=1).map(move |x| Point::new(x, y)))
+                    .filter(|d| *d != Point::zero() && noisy.contains(&(**p + *d)))
+                    .count();
+                **p == center || neighbours >= 4
+            })
+            .copied()
+            .collect();
+
+        let mut connected = HashSet::new();
+        let mut open = vec![center];
+        while let Some(p) = open.pop() {
+            if smoothed.contains(&p) && connected.insert(p) {
+                open.extend([
+                    p + Point::new(-1, 0),
+                    p + Point::new(1, 0),
+                    p + Point::new(0, -1),
+                    p + Point::new(0, 1),
+                ]);
+            }
+        }
+        self.tiles.retain(|p| connected.contains(p));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHAPES: [RoomShape; 6] = [
+        RoomShape::Rectangle,
+        RoomShape::Circle,
+        RoomShape::Cross,
+        RoomShape::LShaped,
+        RoomShape::Cavern,
+        RoomShape::Pillared,
+    ];
+
+    // `Rect::for_each` includes the far edges, so rooms do too
+    fn inside(bounds: &Rect, p: Point) -> bool {
+        p.x >= bounds.x1 && p.x <= bounds.x2 && p.y >= bounds.y1 && p.y <= bounds.y2
+    }
+
+    #[test]
+    fn every_shape_stays_inside_its_bounds() {
+        let mut rng = RandomNumberGenerator::seeded(3);
+        for shape in SHAPES {
+            for (w, h) in [(5, 5), (8, 6), (11, 7)] {
+                let bounds = Rect::with_size(10, 20, w, h);
+                let room = Room::new(bounds, shape, &mut rng);
+                assert!(!room.tiles.is_empty());
+                assert!(room.tiles.iter().all(|p| inside(&bounds, *p)), "{shape:?}");
+                assert!(room.contains(room.center()));
+            }
+        }
+    }
+
+    #[test]
+    fn pillars_never_stand_on_the_outer_ring() {
+        let bounds = Rect::with_size(0, 0, 9, 7);
+        let room = Room::new(bounds, RoomShape::Pillared, &mut RandomNumberGenerator::seeded(1));
+        bounds.for_each(|p| {
+            let on_ring =
+                p.x == bounds.x1 || p.x == bounds.x2 || p.y == bounds.y1 || p.y == bounds.y2;
+            if on_ring {
+                assert!(room.contains(p), "{p:?} is missing from the ring");
+            }
+        });
+        // Pillars every other tile inside the ring, at x 2, 4, 6 and y 2, 4
+        assert_eq!(room.area(), 10 * 8 - 6);
+    }
+
+    #[test]
+    fn caverns_keep_only_tiles_connected_to_the_centre() {
+        let bounds = Rect::with_size(0, 0, 12, 10);
+        for seed in 0..20 {
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            let room = Room::new(bounds, RoomShape::Cavern, &mut rng);
+
+            let mut reached = HashSet::new();
+            let mut open = vec![room.center()];
+            while let Some(p) = open.pop() {
+                if room.contains(p) && reached.insert(p) {
+                    open.extend(CARDINALS.iter().map(|d| p + *d));
+                }
+            }
+            assert_eq!(reached.len(), room.area());
+        }
+    }
+}
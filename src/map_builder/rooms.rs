@@ -1,26 +1,41 @@
 use super::MapArchitect;
 use crate::prelude::*;
 
-pub struct RoomsArchitect {}
+pub struct RoomsArchitect {
+    pub corridors: CorridorStyle,
+}
+
+// About one monster per room on a full size map
+const MONSTER_DENSITY: f32 = 0.025;
 
 impl MapArchitect for RoomsArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
+    fn new(
+        &mut self,
+        rng: &mut RandomNumberGenerator,
+        settings: &GenerationSettings,
+    ) -> Result<MapBuilder, MapGenError> {
         let mut mb = MapBuilder {
-            map: Map::new(),
+            map: Map::with_size(settings.width, settings.height),
             rooms: Vec::new(),
+            secret_rooms: Vec::new(),
+            depth: settings.depth,
             monster_spawns: Vec::new(),
+            loot: Vec::new(),
             player_start: Point::zero(),
             amulet_start: Point::zero(),
+            props: Vec::new(),
+            snapshots: settings.record_snapshots.then(Vec::new),
             // theme: super::themes::DungeonTheme::new()
         };
         mb.fill(TileType::Wall);
+        mb.take_snapshot();
         mb.build_random_rooms(rng);
-        mb.build_corridors(rng);
-        mb.player_start = mb.rooms[0].center();
-        mb.amulet_start = mb.find_most_distant();
-        for room in mb.rooms.iter().skip(1) {
-            mb.monster_spawns.push(room.center());
-        }
-        mb
+        mb.build_corridors(self.corridors, rng);
+        mb.place_doors();
+        mb.take_snapshot();
+        mb.player_start = mb.rooms.first().ok_or(MapGenError::NoRooms)?.center();
+        mb.amulet_start = mb.find_most_distant()?;
+        mb.spawn_monsters(settings.monster_density.unwrap_or(MONSTER_DENSITY), rng);
+        Ok(mb)
     }
 }
@@ -0,0 +1,146 @@
+use crate::prelude::*;
+use super::loot::roll_treasure;
+
+/// Carves up to `count` rooms out of solid rock, each behind a hidden door next to a
+/// reachable floor tile. They hold vault treasure and are left out of `rooms`, so
+/// nothing else about the level depends on them being found.
+pub fn apply_secret_rooms(mb: &mut MapBuilder, count: usize, rng: &mut RandomNumberGenerator) {
+    let dijkstra_map = mb.distance_map();
+
+    let mut attempts = 0;
+    while mb.secret_rooms.len() < count && attempts < 200 {
+        attempts += 1;
+        let bounds = Rect::with_size(
+            rng.range(2, mb.map.width - 8),
+            rng.range(2, mb.map.height - 7),
+            rng.range(2, 5),
+            rng.range(2, 4),
+        );
+
+        if !is_solid_rock(mb, &bounds) {
+            continue;
+        }
+
+        let room = Room::new(bounds, RoomShape::Rectangle, rng);
+        let entrances = entrances(mb, &room, &dijkstra_map);
+        let Some(entrance) = rng.random_slice_index(&entrances).map(|i| entrances[i]) else {
+            continue;
+        };
+
+        for tile in room.tiles.iter() {
+            let idx = mb.map.idx(tile.x, tile.y);
+            mb.map.tiles[idx] = TileType::Floor;
+        }
+        let idx = mb.map.idx(entrance.x, entrance.y);
+        mb.map.tiles[idx] = TileType::HiddenDoor;
+
+        for _ in 0..rng.range(1, 3) {
+            if let Some(i) = rng.random_slice_index(&room.tiles) {
+                let position = room.tiles[i];
+                if !mb.has_loot_at(position) {
+                    let kind = roll_treasure(mb.depth, rng);
+                    mb.loot.push(LootSpawn { position, kind });
+                }
+            }
+        }
+        mb.secret_rooms.push(room);
+    }
+}
+
+// Solid rock all round, so the hidden door is the only way in. Rooms cover both edges
+// of their bounds, so the ring sits one tile outside each of them.
+fn is_solid_rock(mb: &MapBuilder, bounds: &Rect) -> bool {
+    let ring = Rect::with_exact(bounds.x1 - 1, bounds.y1 - 1, bounds.x2 + 1, bounds.y2 + 1);
+    let mut solid = true;
+    ring.for_each(|p| {
+        if mb.map.tiles[mb.map.idx(p.x, p.y)] != TileType::Wall
+            || p == mb.amulet_start
+            || mb.rooms.iter().any(|r| r.contains(p))
+        {
+            solid = false;
+        }
+    });
+    solid
+}
+
+// Wall tiles next to `room` with floor the player can already reach straight beyond them
+fn entrances(mb: &MapBuilder, room: &Room, distances: &DijkstraMap) -> Vec<Point> {
+    room.tiles
+        .iter()
+        .flat_map(|tile| CARDINALS.iter().map(move |d| (*tile + *d, *tile + *d + *d)))
+        .filter(|(door, outside)| {
+            !room.contains(*door)
+                && mb.map.try_idx(*outside).is_some_and(|idx| {
+                    mb.map.tiles[idx] == TileType::Floor && distances.map[idx] < f32::MAX
+                })
+        })
+        .map(|(door, _)| door)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A corridor across the top of the map, all of it reachable from the start
+    fn corridor() -> MapBuilder {
+        let mut mb = MapBuilder::blank(30, 20);
+        mb.carve_rect(Point::new(1, 2), Point::new(28, 2));
+        mb.player_start = Point::new(1, 2);
+        mb.amulet_start = Point::new(28, 2);
+        mb
+    }
+
+    #[test]
+    fn secret_rooms_need_solid_rock_all_round() {
+        let mut mb = corridor();
+        // Room tiles cover 10..=13 by 5..=7, so the ring is 9..=14 by 4..=8
+        let bounds = Rect::with_size(10, 5, 3, 2);
+        assert!(is_solid_rock(&mb, &bounds));
+
+        let idx = mb.map.idx(14, 8);
+        mb.map.tiles[idx] = TileType::Floor;
+        assert!(!is_solid_rock(&mb, &bounds));
+        mb.map.tiles[idx] = TileType::Wall;
+
+        mb.amulet_start = Point::new(9, 4);
+        assert!(!is_solid_rock(&mb, &bounds));
+    }
+
+    #[test]
+    fn entrances_only_open_onto_reachable_floor() {
+        let mut mb = corridor();
+        // Floor below the room that nothing leads to
+        mb.carve_rect(Point::new(10, 8), Point::new(13, 8));
+        let bounds = Rect::with_size(10, 4, 3, 2);
+        let room = Room::new(bounds, RoomShape::Rectangle, &mut RandomNumberGenerator::seeded(0));
+
+        let entrances = entrances(&mb, &room, &mb.distance_map());
+        assert_eq!(entrances, (10..=13).map(|x| Point::new(x, 3)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn secret_rooms_sit_behind_a_single_hidden_door() {
+        for seed in 0..10 {
+            let mut mb = corridor();
+            apply_secret_rooms(&mut mb, 1, &mut RandomNumberGenerator::seeded(seed));
+            assert_eq!(mb.secret_rooms.len(), 1);
+            assert!(!mb.loot.is_empty());
+
+            let hidden: Vec<Point> = (0..mb.map.tiles.len())
+                .filter(|idx| mb.map.tiles[*idx] == TileType::HiddenDoor)
+                .map(|idx| mb.map.index_to_point2d(idx))
+                .collect();
+            assert_eq!(hidden.len(), 1);
+
+            let room = mb.secret_rooms[0].clone();
+            let reachable = |mb: &MapBuilder| {
+                let distances = mb.distance_map();
+                room.tiles.iter().all(|p| distances.map[mb.map.point2d_to_index(*p)] < f32::MAX)
+            };
+            assert!(!reachable(&mb));
+            assert!(mb.map.reveal_hidden_door(hidden[0]));
+            assert!(reachable(&mb));
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::prelude::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum MonsterKind {
+    Goblin,
+    Orc,
+    Skeleton,
+    Ogre,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MonsterSpawn {
+    pub position: Point,
+    pub kind: MonsterKind,
+}
+
+pub struct SpawnTableEntry {
+    pub kind: MonsterKind,
+    pub weight: i32,
+    pub min_depth: i32,
+    pub max_depth: i32,
+}
+
+const MONSTER_TABLE: [SpawnTableEntry; 4] = [
+    SpawnTableEntry { kind: MonsterKind::Goblin, weight: 10, min_depth: 1, max_depth: 3 },
+    SpawnTableEntry { kind: MonsterKind::Orc, weight: 6, min_depth: 1, max_depth: 6 },
+    SpawnTableEntry { kind: MonsterKind::Skeleton, weight: 4, min_depth: 2, max_depth: 8 },
+    SpawnTableEntry { kind: MonsterKind::Ogre, weight: 2, min_depth: 3, max_depth: i32::MAX },
+];
+
+/// How dangerous a spot is, judged by how far the player has to walk to reach it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DifficultyBand {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl DifficultyBand {
+    // Spawns roll on the table of a shallower or deeper level
+    fn depth_offset(&self) -> i32 {
+        match self {
+            DifficultyBand::Easy => -1,
+            DifficultyBand::Medium => 0,
+            DifficultyBand::Hard => 1,
+        }
+    }
+}
+
+// Anything this close to the amulet guards it
+const AMULET_GUARD_RADIUS: f32 = 8.0;
+// No monster waits within this many steps of the start
+const MIN_START_DISTANCE: f32 = 10.0;
+
+/// Path distances from the player start, split into thirds of the longest walk.
+pub(super) struct DifficultyBands {
+    distances: DijkstraMap,
+    dimensions: Point,
+    furthest: f32,
+    amulet: Point,
+}
+
+impl DifficultyBands {
+    fn distance(&self, position: Point) -> f32 {
+        let in_bounds = position.x >= 0
+            && position.x < self.dimensions.x
+            && position.y >= 0
+            && position.y < self.dimensions.y;
+        if !in_bounds {
+            return f32::MAX;
+        }
+        self.distances.map[(position.y * self.dimensions.x + position.x) as usize]
+    }
+
+    pub(super) fn band_at(&self, position: Point) -> DifficultyBand {
+        let distance = self.distance(position);
+        // Unreachable pockets such as sealed vaults count as deep
+        if distance == f32::MAX
+            || DistanceAlg::Pythagoras.distance2d(position, self.amulet) <= AMULET_GUARD_RADIUS
+        {
+            return DifficultyBand::Hard;
+        }
+        let fraction = if self.furthest > 0.0 { distance / self.furthest } else { 0.0 };
+        if fraction < 1.0 / 3.0 {
+            DifficultyBand::Easy
+        } else if fraction < 2.0 / 3.0 {
+            DifficultyBand::Medium
+        } else {
+            DifficultyBand::Hard
+        }
+    }
+}
+
+/// Picks a monster kind for `depth`, weighted by the entries available at that depth.
+pub fn roll_monster(depth: i32, rng: &mut RandomNumberGenerator) -> MonsterKind {
+    let available: Vec<&SpawnTableEntry> = MONSTER_TABLE
+        .iter()
+        .filter(|e| depth >= e.min_depth && depth <= e.max_depth)
+        .collect();
+    let total: i32 = available.iter().map(|e| e.weight).sum();
+    if total == 0 {
+        return MONSTER_TABLE[0].kind;
+    }
+
+    let mut roll = rng.range(0, total);
+    for entry in available {
+        if roll < entry.weight {
+            return entry.kind;
+        }
+        roll -= entry.weight;
+    }
+    unreachable!("roll is always below the total weight")
+}
+
+impl MapBuilder {
+    /// Bands for the current `player_start` and `amulet_start`, so set both first.
+    pub(super) fn difficulty_bands(&self) -> DifficultyBands {
+        let distances = self.distance_map();
+        let furthest = distances
+            .map
+            .iter()
+            .filter(|d| **d < f32::MAX)
+            .fold(0.0f32, |a, b| a.max(*b));
+        DifficultyBands {
+            distances,
+            dimensions: Point::new(self.map.width, self.map.height),
+            furthest,
+            amulet: self.amulet_start,
+        }
+    }
+
+    pub(super) fn spawn_monster(
+        &mut self,
+        position: Point,
+        band: DifficultyBand,
+        rng: &mut RandomNumberGenerator,
+    ) {
+        let depth = (self.depth + band.depth_offset()).max(1);
+        let kind = roll_monster(depth, rng);
+        self.monster_spawns.push(MonsterSpawn { position, kind });
+    }
+
+    pub fn has_monster_at(&self, position: Point) -> bool {
+        self.monster_spawns.iter().any(|s| s.position == position)
+    }
+
+    /// Scatters monsters over `density` of the reachable floor tiles more than 10 steps from
+    /// the start, harder the further they are. Small maps simply get fewer monsters.
+    pub(super) fn spawn_monsters(&mut self, density: f32, rng: &mut RandomNumberGenerator) {
+        let bands = self.difficulty_bands();
+        let mut spawnable_tiles: Vec<Point> = self
+            .map
+            .tiles
+            .iter()
+            .enumerate()
+            .map(|(idx, t)| (self.map.index_to_point2d(idx), t))
+            .filter(|(p, t)| {
+                let distance = bands.distance(*p);
+                **t == TileType::Floor && distance < f32::MAX && distance > MIN_START_DISTANCE
+            })
+            .map(|(p, _)| p)
+            .collect();
+
+        let count = (spawnable_tiles.len() as f32 * density) as usize;
+        for _ in 0..count {
+            let Some(target_index) = rng.random_slice_index(&spawnable_tiles) else {
+                break;
+            };
+            let position = spawnable_tiles.remove(target_index);
+            self.spawn_monster(position, bands.band_at(position), rng);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn roll_counts(depth: i32, rolls: usize) -> HashMap<MonsterKind, usize> {
+        let mut rng = RandomNumberGenerator::seeded(1);
+        let mut counts = HashMap::new();
+        for _ in 0..rolls {
+            *counts.entry(roll_monster(depth, &mut rng)).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn rolls_follow_the_table_weights() {
+        let counts = roll_counts(1, 16_000);
+        assert_eq!(counts.len(), 2, "only goblins and orcs live on depth 1");
+        // Weighted 10 to 6
+        let goblins = counts[&MonsterKind::Goblin] as f32 / 16_000.0;
+        assert!((0.58..0.67).contains(&goblins), "goblin share {goblins}");
+    }
+
+    #[test]
+    fn rolls_respect_depth_ranges() {
+        assert_eq!(roll_counts(3, 2_000).len(), 4);
+        let deep = roll_counts(9, 200);
+        assert_eq!(deep.keys().collect::<Vec<_>>(), vec![&MonsterKind::Ogre]);
+        // A depth nothing is listed for still gets a monster
+        assert_eq!(roll_counts(0, 10)[&MonsterKind::Goblin], 10);
+    }
+
+    // A straight corridor with the start at the west end and the amulet at the east end
+    fn corridor() -> MapBuilder {
+        let mut mb = MapBuilder::blank(40, 5);
+        mb.carve_rect(Point::new(1, 2), Point::new(38, 2));
+        mb.player_start = Point::new(1, 2);
+        mb.amulet_start = Point::new(38, 2);
+        mb
+    }
+
+    #[test]
+    fn bands_grow_harder_along_the_path() {
+        let bands = corridor().difficulty_bands();
+        assert_eq!(bands.band_at(Point::new(5, 2)), DifficultyBand::Easy);
+        assert_eq!(bands.band_at(Point::new(18, 2)), DifficultyBand::Medium);
+        assert_eq!(bands.band_at(Point::new(28, 2)), DifficultyBand::Hard);
+    }
+
+    #[test]
+    fn amulet_surroundings_and_unreachable_tiles_are_hard() {
+        let bands = corridor().difficulty_bands();
+        assert_eq!(bands.band_at(Point::new(31, 2)), DifficultyBand::Hard);
+        assert_eq!(bands.band_at(Point::new(5, 0)), DifficultyBand::Hard);
+        assert_eq!(bands.band_at(Point::new(-3, 2)), DifficultyBand::Hard);
+    }
+
+    #[test]
+    fn monsters_keep_away_from_the_start() {
+        let mut mb = corridor();
+        let mut rng = RandomNumberGenerator::seeded(3);
+        mb.spawn_monsters(1.0, &mut rng);
+        // Every floor tile more than MIN_START_DISTANCE steps out, and nothing else
+        assert_eq!(mb.monster_spawns.len(), 27);
+        for spawn in mb.monster_spawns.iter() {
+            assert!(spawn.position.x - 1 > MIN_START_DISTANCE as i32);
+            assert_eq!(spawn.position.y, 2);
+        }
+    }
+}
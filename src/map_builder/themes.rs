@@ -2,6 +2,25 @@ use crate::prelude::*;
 
 pub struct DungeonTheme {}
 
+/// Chances are rolled out of 100 per candidate tile.
+pub struct DecorationRules {
+    pub rubble_chance: i32,
+    pub bones_chance: i32,
+    pub torch_chance: i32,
+    pub banner_min_room_area: usize,
+}
+
+impl DungeonTheme {
+    pub fn decoration_rules() -> DecorationRules {
+        DecorationRules {
+            rubble_chance: 3,
+            bones_chance: 1,
+            torch_chance: 4,
+            banner_min_room_area: 36,
+        }
+    }
+}
+
 // impl DungeonTheme {
 //     pub fn new() -> Box<dyn MapTheme> {
 //         Box::new(Self {})